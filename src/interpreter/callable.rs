@@ -0,0 +1,231 @@
+use super::{
+    environment::Environment,
+    error::{HostError, RuntimeError},
+    value::Value,
+    Completion, Interpreter,
+};
+use crate::{
+    lox::{
+        self,
+        host::HostFunction,
+        native::{BoundMethod, ClassDefinition},
+    },
+    parser::statements::Function,
+    scanner::tokens::Span,
+    symbol::Symbol,
+};
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug, Display, Formatter},
+    rc::Rc,
+};
+
+/// Anything that can be called from Lox with a parenthesised argument list.
+pub trait Callable: Debug + Display {
+    /// The number of arguments the callable expects.
+    fn arity(&self) -> usize;
+    /// Whether the callable also takes any number of arguments beyond [`Callable::arity`].
+    fn is_variadic(&self) -> bool {
+        false
+    }
+    /// Invokes the callable. The interpreter has already checked that `arguments` matches
+    /// [`Callable::arity`]. `span` locates the call, for errors raised by the call itself.
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError>;
+}
+
+/// A function declared in Lox, along with the environment it closes over.
+pub struct LoxFunction {
+    declaration: Rc<Function>,
+    closure: Rc<RefCell<Environment>>,
+    /// Initializers always return `this`, even from an empty `return;`.
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn new(
+        declaration: Rc<Function>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// Creates a copy of this method whose closure binds `this` to `instance`.
+    pub fn bind(&self, instance: Value) -> LoxFunction {
+        let environment = Environment::with_enclosing(self.closure.clone());
+        environment.borrow_mut().define(Symbol::THIS, instance);
+        LoxFunction::new(self.declaration.clone(), environment, self.is_initializer)
+    }
+}
+
+impl Callable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        _: Span,
+    ) -> Result<Value, RuntimeError> {
+        let environment = Environment::with_enclosing(self.closure.clone());
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.borrow_mut().define(param.symbol(), argument);
+        }
+        let completion = interpreter.execute_block(&self.declaration.body, environment)?;
+        if self.is_initializer {
+            return Ok(self
+                .closure
+                .borrow()
+                .get_at(0, Symbol::THIS)
+                .unwrap_or(Value::Nil));
+        }
+        match completion {
+            Completion::Return(value) => Ok(value),
+            Completion::Normal => Ok(Value::Nil),
+        }
+    }
+}
+
+// The closure usually contains this function, so a derived impl would recurse forever.
+impl Debug for LoxFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoxFunction")
+            .field("name", &self.declaration.name.symbol())
+            .finish()
+    }
+}
+
+impl Display for LoxFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name)
+    }
+}
+
+/// A function implemented in Rust and exposed to Lox as a global.
+#[derive(Clone, Copy)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Value,
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        self: Rc<Self>,
+        _: &mut Interpreter,
+        arguments: Vec<Value>,
+        _: Span,
+    ) -> Result<Value, RuntimeError> {
+        Ok((self.function)(&arguments))
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+impl Display for NativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+impl Callable for HostFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn is_variadic(&self) -> bool {
+        self.variadic
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        call_host(interpreter, &self.name, arguments, span, |arguments| {
+            (self.function)(arguments)
+        })
+    }
+}
+
+impl Callable for ClassDefinition {
+    fn arity(&self) -> usize {
+        ClassDefinition::arity(self)
+    }
+
+    fn is_variadic(&self) -> bool {
+        ClassDefinition::is_variadic(self)
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        call_host(interpreter, self.name(), arguments, span, |arguments| {
+            self.construct(arguments)
+        })
+    }
+}
+
+impl Callable for BoundMethod {
+    fn arity(&self) -> usize {
+        BoundMethod::arity(self)
+    }
+
+    fn is_variadic(&self) -> bool {
+        BoundMethod::is_variadic(self)
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        call_host(interpreter, self.name(), arguments, span, |arguments| {
+            BoundMethod::call(&self, arguments)
+        })
+    }
+}
+
+/// Calls into the host with `arguments` converted for it, and converts the result back.
+fn call_host(
+    interpreter: &Interpreter,
+    name: &str,
+    arguments: Vec<Value>,
+    span: Span,
+    function: impl FnOnce(Vec<lox::value::Value>) -> Result<lox::value::Value, HostError>,
+) -> Result<Value, RuntimeError> {
+    let arguments = arguments
+        .into_iter()
+        .map(|argument| interpreter.export(argument))
+        .collect();
+    function(arguments)
+        .and_then(|result| interpreter.import(result))
+        .map_err(|error| error.at(name, span))
+}
@@ -0,0 +1,120 @@
+use super::{
+    callable::{Callable, LoxFunction},
+    error::RuntimeError,
+    value::Value,
+    Interpreter,
+};
+use crate::{
+    scanner::tokens::{Span, SpannedToken},
+    symbol::Symbol,
+};
+use ahash::AHashMap;
+use std::{
+    cell::RefCell,
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+#[derive(Debug)]
+pub struct LoxClass {
+    pub name: Symbol,
+    pub superclass: Option<Rc<LoxClass>>,
+    methods: AHashMap<Symbol, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: Symbol,
+        superclass: Option<Rc<LoxClass>>,
+        methods: AHashMap<Symbol, Rc<LoxFunction>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    /// Looks up a method on this class, falling back to its superclasses.
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(&name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+}
+
+/// Calling a class constructs a new instance, running its `init` method if it has one.
+impl Callable for LoxClass {
+    fn arity(&self) -> usize {
+        self.find_method(Symbol::INIT)
+            .map_or(0, |init| init.arity())
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        let initializer = self.find_method(Symbol::INIT);
+        let instance = Value::Instance(Rc::new(RefCell::new(LoxInstance::new(self))));
+        if let Some(initializer) = initializer {
+            Rc::new(initializer.bind(instance.clone())).call(interpreter, arguments, span)?;
+        }
+        Ok(instance)
+    }
+}
+
+impl Display for LoxClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: AHashMap<Symbol, Value>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: AHashMap::new(),
+        }
+    }
+
+    /// Gets a property of `instance`. Fields shadow methods, and methods are bound to the instance
+    /// they were accessed from.
+    pub fn get(
+        instance: &Rc<RefCell<LoxInstance>>,
+        name: &SpannedToken,
+    ) -> Result<Value, RuntimeError> {
+        let key = name.symbol();
+        if let Some(value) = instance.borrow().fields.get(&key) {
+            return Ok(value.clone());
+        }
+        let method = instance.borrow().class.find_method(key);
+        match method {
+            Some(method) => Ok(Value::Callable(Rc::new(
+                method.bind(Value::Instance(instance.clone())),
+            ))),
+            None => Err(RuntimeError::UndefinedProperty {
+                name: key.to_string(),
+                span: name.span,
+            }),
+        }
+    }
+
+    pub fn set(&mut self, name: &SpannedToken, value: Value) {
+        self.fields.insert(name.symbol(), value);
+    }
+}
+
+impl Display for LoxInstance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
@@ -0,0 +1,71 @@
+use super::value::Value;
+use crate::symbol::Symbol;
+use ahash::AHashMap;
+use std::{cell::RefCell, rc::Rc};
+
+/// A scope of variable bindings, chained to the scope that encloses it. The global scope is the
+/// only one without an enclosing environment.
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: AHashMap<Symbol, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self::default()))
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            values: AHashMap::new(),
+            enclosing: Some(enclosing),
+        }))
+    }
+
+    /// Binds `name` in this scope, shadowing any outer binding and replacing any existing one.
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    /// Gets `name` from the innermost scope that declares it.
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        match self.values.get(&name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.borrow().get(name),
+        }
+    }
+
+    /// Reassigns an existing binding in the innermost scope that declares `name`, returning
+    /// whether one was found.
+    pub fn assign(&mut self, name: Symbol, value: Value) -> bool {
+        match self.values.get_mut(&name) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+                None => false,
+            },
+        }
+    }
+
+    /// Gets `name` from the scope exactly `distance` scopes out, as computed by the resolver.
+    pub fn get_at(&self, distance: usize, name: Symbol) -> Option<Value> {
+        if distance == 0 {
+            self.values.get(&name).cloned()
+        } else {
+            self.enclosing.as_ref()?.borrow().get_at(distance - 1, name)
+        }
+    }
+
+    /// Reassigns `name` in the scope exactly `distance` scopes out, as computed by the resolver.
+    pub fn assign_at(&mut self, distance: usize, name: Symbol, value: Value) {
+        if distance == 0 {
+            self.values.insert(name, value);
+        } else if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow_mut().assign_at(distance - 1, name, value);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::scanner::tokens::{Span, Token};
+
+#[derive(thiserror::Error, Debug)]
+pub enum RuntimeError {
+    #[error("Error: Operand of '{operator}' must be a number at {span}")]
+    OperandMustBeNumber { operator: Token, span: Span },
+    #[error("Error: Operands of '{operator}' must be numbers at {span}")]
+    OperandsMustBeNumbers { operator: Token, span: Span },
+    #[error("Error: Operands of '{operator}' must be two numbers or two strings at {span}")]
+    OperandsMustBeNumbersOrStrings { operator: Token, span: Span },
+    #[error("Error: Undefined variable '{name}' at {span}")]
+    UndefinedVariable { name: String, span: Span },
+    #[error("Error: Can only call functions and classes at {span}")]
+    NotCallable { span: Span },
+    #[error("Error: Only instances have properties at {span}")]
+    OnlyInstancesHaveProperties { span: Span },
+    #[error("Error: Only instances have fields at {span}")]
+    OnlyInstancesHaveFields { span: Span },
+    #[error("Error: Undefined property '{name}' at {span}")]
+    UndefinedProperty { name: String, span: Span },
+    #[error("Error: Superclass must be a class at {span}")]
+    SuperclassMustBeClass { span: Span },
+    #[error("Error: Expected {expected} arguments but got {found} at {span}")]
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    #[error("Error: Expected at least {expected} arguments but got {found} at {span}")]
+    TooFewArguments {
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    #[error("Error: Expected {expected} for argument {index} of '{function}' at {span}")]
+    InvalidArgument {
+        function: String,
+        /// One-based position of the argument.
+        index: usize,
+        expected: String,
+        span: Span,
+    },
+    #[error("Error: '{function}' returned an object from a different interpreter at {span}")]
+    ForeignObject { function: String, span: Span },
+    #[error("Error: Expected {expected} for '{name}' at {span}")]
+    InvalidValue {
+        name: String,
+        expected: String,
+        span: Span,
+    },
+    #[error("Error: Can't assign to read-only property '{name}' at {span}")]
+    ReadOnlyProperty { name: String, span: Span },
+    #[error("Error: Native class '{class}' can't be constructed from Lox at {span}")]
+    NoConstructor { class: String, span: Span },
+    #[error("Error: Stack overflow at {span}")]
+    StackOverflow { span: Span },
+    #[error("Error: Exceeded the limit of {limit} steps")]
+    StepLimitExceeded { limit: u64 },
+    #[error("Error: Exceeded the heap limit of {limit} bytes")]
+    HeapLimitExceeded { limit: usize },
+    #[error("Error: Interrupted")]
+    Interrupted,
+}
+
+/// Why a host function or native class failed, before the backend knows where it was used.
+#[derive(Debug, Clone)]
+pub enum HostError {
+    InvalidArgument {
+        index: usize,
+        expected: String,
+    },
+    ForeignObject,
+    /// A value assigned to a native field couldn't be converted.
+    InvalidValue {
+        expected: String,
+    },
+    ReadOnlyProperty,
+    UndefinedProperty,
+    NoConstructor,
+}
+
+impl HostError {
+    /// The runtime error for this failure in the use of the function, field or class `name` at
+    /// `span`.
+    pub(crate) fn at(self, name: &str, span: Span) -> RuntimeError {
+        let name = name.to_string();
+        match self {
+            HostError::InvalidArgument { index, expected } => RuntimeError::InvalidArgument {
+                function: name,
+                index,
+                expected,
+                span,
+            },
+            HostError::ForeignObject => RuntimeError::ForeignObject {
+                function: name,
+                span,
+            },
+            HostError::InvalidValue { expected } => RuntimeError::InvalidValue {
+                name,
+                expected,
+                span,
+            },
+            HostError::ReadOnlyProperty => RuntimeError::ReadOnlyProperty { name, span },
+            HostError::UndefinedProperty => RuntimeError::UndefinedProperty { name, span },
+            HostError::NoConstructor => RuntimeError::NoConstructor { class: name, span },
+        }
+    }
+}
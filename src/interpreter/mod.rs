@@ -1,85 +1,502 @@
-use crate::{parser::expressions::Expression, scanner::tokens::Token};
-use core::panic;
+use self::{
+    callable::{Callable, LoxFunction},
+    class::{LoxClass, LoxInstance},
+    environment::Environment,
+    error::{HostError, RuntimeError},
+    natives::NATIVES,
+    value::{LoxString, Value},
+};
+use crate::{
+    lox::{
+        self,
+        limits::{InterruptHandle, Limits},
+        native::Property,
+        value::{next_owner, Object, ObjectKind},
+    },
+    parser::{
+        expressions::{ExprId, Expression},
+        statements::Stmt,
+    },
+    resolver::Locals,
+    scanner::tokens::{SpannedToken, Token},
+    symbol::Symbol,
+};
+use std::{cell::RefCell, rc::Rc};
 
-pub trait Interpretation<T> {
-    fn interpret(&self) -> T;
+pub mod callable;
+pub mod class;
+pub mod environment;
+pub mod error;
+pub mod natives;
+pub mod value;
+
+/// Stack left below which a statement moves onto a new segment before running, as statements and
+/// the calls in them recurse on the Rust stack. Enough for the most deeply nested expression the
+/// parser allows, even in a debug build.
+const STACK_RED_ZONE: usize = 4 * 1024 * 1024;
+/// Size of each stack segment added once the stack runs low.
+const STACK_SEGMENT: usize = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct Interpreter {
+    /// The outermost scope, where natives live.
+    globals: Rc<RefCell<Environment>>,
+    /// The scope statements are currently executing in.
+    environment: Rc<RefCell<Environment>>,
+    /// Resolved scope distances of every local variable reference seen so far.
+    locals: Locals,
+    /// Distinguishes objects handed to the host from those of other backends.
+    id: usize,
+    limits: Limits,
+    interrupt: InterruptHandle,
+    /// Statements executed so far in the current run.
+    steps: u64,
+    /// Calls in progress.
+    depth: usize,
 }
 
-impl Interpretation<bool> for Expression {
-    fn interpret(&self) -> bool {
-        match self {
-            Expression::Unary(u) => {
-                if let Token::Bang = u.operator {
-                    let result: bool = u.right.interpret();
-                    !result
-                } else {
-                    panic!(
-                        "Expected '!' on LHS of unary expression, found {}",
-                        u.operator
-                    )
+/// How a statement finished executing.
+enum Completion {
+    Normal,
+    /// A `return` statement was executed, and should unwind to the enclosing function call.
+    Return(Value),
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        let globals = Environment::new();
+        for native in NATIVES {
+            globals.borrow_mut().define(
+                Symbol::intern(native.name),
+                Value::Callable(Rc::new(*native)),
+            );
+        }
+        Self {
+            environment: globals.clone(),
+            globals,
+            locals: Locals::new(),
+            id: next_owner(),
+            limits: Limits::default(),
+            interrupt: InterruptHandle::default(),
+            steps: 0,
+            depth: 0,
+        }
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Executes `statements`, using `locals` from [`crate::resolver::resolve`] to look up
+    /// variables.
+    pub fn interpret(&mut self, statements: &[Stmt], locals: Locals) -> Result<(), RuntimeError> {
+        self.locals.extend(locals);
+        self.start_run();
+        self.execute_all(statements)?;
+        Ok(())
+    }
+
+    /// Evaluates an expression resolved by [`crate::resolver::resolve_expression`].
+    pub fn evaluate_expression(
+        &mut self,
+        expression: &Expression,
+        locals: Locals,
+    ) -> Result<Value, RuntimeError> {
+        self.locals.extend(locals);
+        self.start_run();
+        self.evaluate(expression)
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    fn start_run(&mut self) {
+        self.steps = 0;
+        self.depth = 0;
+    }
+
+    /// Counts a statement against the step limit, and checks for an interrupt.
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        match self.limits.steps {
+            Some(limit) if self.steps > limit => Err(RuntimeError::StepLimitExceeded { limit }),
+            _ if self.interrupt.take() => Err(RuntimeError::Interrupted),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn get_global(&self, name: Symbol) -> Option<Value> {
+        self.globals.borrow().get(name)
+    }
+
+    /// Defines a global variable, or overwrites it if it already exists.
+    pub fn set_global(&mut self, name: Symbol, value: Value) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    /// Converts a value for the host.
+    pub fn export(&self, value: Value) -> lox::value::Value {
+        use lox::value::Value as Host;
+        match value {
+            Value::Nil => Host::Nil,
+            Value::Bool(b) => Host::Bool(b),
+            Value::Number(n) => Host::Number(n),
+            Value::String(s) => Host::String(s.as_str().to_string()),
+            Value::Native(instance) => Host::Native(instance),
+            object => Host::Object(Object {
+                owner: self.id,
+                kind: ObjectKind::TreeWalk(object),
+            }),
+        }
+    }
+
+    /// Converts a value from the host, which can't hold an object from another backend.
+    pub fn import(&self, value: lox::value::Value) -> Result<Value, HostError> {
+        use lox::value::Value as Host;
+        Ok(match value {
+            Host::Nil => Value::Nil,
+            Host::Bool(b) => Value::Bool(b),
+            Host::Number(n) => Value::Number(n),
+            Host::String(s) => Value::String(s.into()),
+            Host::Native(instance) => Value::Native(instance),
+            Host::Object(Object {
+                owner,
+                kind: ObjectKind::TreeWalk(object),
+            }) if owner == self.id => object,
+            Host::Object(_) => return Err(HostError::ForeignObject),
+        })
+    }
+
+    /// Executes `statements` in order, stopping early if one of them returns.
+    fn execute_all(&mut self, statements: &[Stmt]) -> Result<Completion, RuntimeError> {
+        for statement in statements {
+            if let Completion::Return(value) = self.execute(statement)? {
+                return Ok(Completion::Return(value));
+            }
+        }
+        Ok(Completion::Normal)
+    }
+
+    fn execute(&mut self, statement: &Stmt) -> Result<Completion, RuntimeError> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            self.execute_on_stack(statement)
+        })
+    }
+
+    fn execute_on_stack(&mut self, statement: &Stmt) -> Result<Completion, RuntimeError> {
+        self.step()?;
+        match statement {
+            Stmt::Expression(s) => {
+                self.evaluate(&s.expression)?;
+            }
+            Stmt::Print(s) => println!("{}", self.evaluate(&s.expression)?),
+            Stmt::Var(s) => {
+                let value = match &s.initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Nil,
+                };
+                self.environment.borrow_mut().define(s.name.symbol(), value);
+            }
+            Stmt::Block(s) => {
+                return self.execute_block(
+                    &s.statements,
+                    Environment::with_enclosing(self.environment.clone()),
+                )
+            }
+            Stmt::If(s) => {
+                if self.evaluate(&s.condition)?.is_truthy() {
+                    return self.execute(&s.then_branch);
+                } else if let Some(else_branch) = &s.else_branch {
+                    return self.execute(else_branch);
+                }
+            }
+            Stmt::While(s) => {
+                while self.evaluate(&s.condition)?.is_truthy() {
+                    if let Completion::Return(value) = self.execute(&s.body)? {
+                        return Ok(Completion::Return(value));
+                    }
                 }
             }
-            _ => panic!(),
+            Stmt::Function(s) => {
+                let function = LoxFunction::new(s.clone(), self.environment.clone(), false);
+                self.environment
+                    .borrow_mut()
+                    .define(s.name.symbol(), Value::Callable(Rc::new(function)));
+            }
+            Stmt::Return(s) => {
+                let value = match &s.value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Ok(Completion::Return(value));
+            }
+            Stmt::Class(s) => {
+                let superclass = match &s.superclass {
+                    Some(superclass) => {
+                        match self.look_up_variable(superclass.id, &superclass.name)? {
+                            Value::Class(class) => Some(class),
+                            _ => {
+                                return Err(RuntimeError::SuperclassMustBeClass {
+                                    span: superclass.name.span,
+                                })
+                            }
+                        }
+                    }
+                    None => None,
+                };
+                self.environment
+                    .borrow_mut()
+                    .define(s.name.symbol(), Value::Nil);
+                // Methods of a subclass close over an extra scope binding `super`.
+                let closure = match &superclass {
+                    Some(superclass) => {
+                        let environment = Environment::with_enclosing(self.environment.clone());
+                        environment
+                            .borrow_mut()
+                            .define(Symbol::SUPER, Value::Class(superclass.clone()));
+                        environment
+                    }
+                    None => self.environment.clone(),
+                };
+                let methods = s
+                    .methods
+                    .iter()
+                    .map(|method| {
+                        let name = method.name.symbol();
+                        let is_initializer = name == Symbol::INIT;
+                        let function =
+                            LoxFunction::new(method.clone(), closure.clone(), is_initializer);
+                        (name, Rc::new(function))
+                    })
+                    .collect();
+                let class = LoxClass::new(s.name.symbol(), superclass, methods);
+                self.environment
+                    .borrow_mut()
+                    .assign(s.name.symbol(), Value::Class(Rc::new(class)));
+            }
         }
+        Ok(Completion::Normal)
     }
-}
 
-impl Interpretation<f64> for Expression {
-    fn interpret(&self) -> f64 {
-        match self {
-            Expression::Unary(u) => {
-                if let Token::Minus = u.operator {
-                    let result: f64 = u.right.interpret();
-                    -result
-                } else {
-                    panic!(
-                        "Expected '-' on LHS of unary expression, found {}",
-                        u.operator
-                    )
+    /// Executes `statements` in `environment`, restoring the current environment afterwards even
+    /// if execution fails.
+    fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Completion, RuntimeError> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = self.execute_all(statements);
+        self.environment = previous;
+        result
+    }
+
+    fn evaluate(&mut self, expression: &Expression) -> Result<Value, RuntimeError> {
+        match expression {
+            Expression::LiteralNil => Ok(Value::Nil),
+            Expression::LiteralBool(b) => Ok(Value::Bool(b.value)),
+            Expression::LiteralNum(n) => Ok(Value::Number(n.value)),
+            Expression::LiteralStr(s) => Ok(Value::String(LoxString::Literal(s.value))),
+            Expression::Grouping(g) => self.evaluate(&g.expression),
+            Expression::Interpolation(i) => {
+                let mut string = String::new();
+                for part in &i.parts {
+                    string.push_str(&self.evaluate(part)?.to_string());
                 }
+                Ok(Value::String(string.into()))
             }
-            Expression::Binary(b) => match b.operator {
-                Token::Minus => {
-                    let lhs: f64 = b.left.interpret();
-                    let rhs: f64 = b.right.interpret();
-                    lhs - rhs
+            Expression::Variable(v) => self.look_up_variable(v.id, &v.name),
+            Expression::Assign(a) => {
+                let value = self.evaluate(&a.value)?;
+                let name = a.name.symbol();
+                match self.locals.get(&a.id) {
+                    Some(&distance) => {
+                        self.environment
+                            .borrow_mut()
+                            .assign_at(distance, name, value.clone());
+                    }
+                    None => {
+                        if !self.globals.borrow_mut().assign(name, value.clone()) {
+                            return Err(RuntimeError::UndefinedVariable {
+                                name: name.to_string(),
+                                span: a.name.span,
+                            });
+                        }
+                    }
                 }
-                Token::Slash => {
-                    let lhs: f64 = b.left.interpret();
-                    let rhs: f64 = b.right.interpret();
-                    lhs / rhs
+                Ok(value)
+            }
+            Expression::Get(g) => match self.evaluate(&g.object)? {
+                Value::Instance(instance) => LoxInstance::get(&instance, &g.name),
+                Value::Native(instance) => {
+                    let name = g.name.symbol().as_str();
+                    match instance.get(name) {
+                        Some(Property::Field(value)) => self
+                            .import(value)
+                            .map_err(|error| error.at(name, g.name.span)),
+                        Some(Property::Method(method)) => Ok(Value::Callable(Rc::new(method))),
+                        None => Err(RuntimeError::UndefinedProperty {
+                            name: name.to_string(),
+                            span: g.name.span,
+                        }),
+                    }
                 }
-                Token::Star => {
-                    let lhs: f64 = b.left.interpret();
-                    let rhs: f64 = b.right.interpret();
-                    lhs * rhs
+                _ => Err(RuntimeError::OnlyInstancesHaveProperties { span: g.name.span }),
+            },
+            Expression::Set(s) => match self.evaluate(&s.object)? {
+                Value::Instance(instance) => {
+                    let value = self.evaluate(&s.value)?;
+                    instance.borrow_mut().set(&s.name, value.clone());
+                    Ok(value)
                 }
-                Token::Plus => {
-                    let lhs: f64 = b.left.interpret();
-                    let rhs: f64 = b.right.interpret();
-                    lhs + rhs
+                Value::Native(instance) => {
+                    let value = self.evaluate(&s.value)?;
+                    let name = s.name.symbol().as_str();
+                    instance
+                        .set(name, self.export(value.clone()))
+                        .map_err(|error| error.at(name, s.name.span))?;
+                    Ok(value)
                 }
-                _ => panic!("Unexpected operator in binary statement"),
+                _ => Err(RuntimeError::OnlyInstancesHaveFields { span: s.name.span }),
             },
-            Expression::LiteralNum(n) => n.value,
-            _ => panic!(&format!("{:?}", self)),
+            Expression::This(t) => self.look_up(t.id, Symbol::THIS, &t.keyword),
+            Expression::Super(s) => {
+                // The resolver puts `super` one scope outside the scope binding `this`.
+                let distance = self.locals.get(&s.id).copied().unwrap_or_default();
+                let superclass = self.environment.borrow().get_at(distance, Symbol::SUPER);
+                let object = self
+                    .environment
+                    .borrow()
+                    .get_at(distance.saturating_sub(1), Symbol::THIS);
+                let method = match superclass {
+                    Some(Value::Class(superclass)) => superclass.find_method(s.method.symbol()),
+                    _ => None,
+                };
+                match (method, object) {
+                    (Some(method), Some(object)) => {
+                        Ok(Value::Callable(Rc::new(method.bind(object))))
+                    }
+                    _ => Err(RuntimeError::UndefinedProperty {
+                        name: s.method.to_string(),
+                        span: s.method.span,
+                    }),
+                }
+            }
+            Expression::Logical(l) => {
+                let left = self.evaluate(&l.left)?;
+                // Short-circuit, returning the operand that decided the result.
+                match (&l.operator.token, left.is_truthy()) {
+                    (Token::Or, true) | (Token::And, false) => Ok(left),
+                    _ => self.evaluate(&l.right),
+                }
+            }
+            Expression::Call(c) => {
+                let callee = self.evaluate(&c.callee)?;
+                let mut arguments = Vec::with_capacity(c.arguments.len());
+                for argument in &c.arguments {
+                    arguments.push(self.evaluate(argument)?);
+                }
+                let callable: Rc<dyn Callable> = match callee {
+                    Value::Callable(callable) => callable,
+                    Value::Class(class) => class,
+                    _ => return Err(RuntimeError::NotCallable { span: c.paren.span }),
+                };
+                let (expected, found) = (callable.arity(), arguments.len());
+                if callable.is_variadic() && found < expected {
+                    return Err(RuntimeError::TooFewArguments {
+                        expected,
+                        found,
+                        span: c.paren.span,
+                    });
+                } else if !callable.is_variadic() && found != expected {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected,
+                        found,
+                        span: c.paren.span,
+                    });
+                }
+                let max_depth = self
+                    .limits
+                    .call_depth
+                    .unwrap_or(Limits::TREE_WALK_CALL_DEPTH);
+                if self.depth >= max_depth {
+                    return Err(RuntimeError::StackOverflow { span: c.paren.span });
+                }
+                self.depth += 1;
+                let result = callable.call(self, arguments, c.paren.span);
+                self.depth -= 1;
+                result
+            }
+            Expression::Unary(u) => {
+                let right = self.evaluate(&u.right)?;
+                match (&u.operator.token, right) {
+                    (Token::Bang, right) => Ok(Value::Bool(!right.is_truthy())),
+                    (Token::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+                    (Token::Minus, _) => Err(RuntimeError::OperandMustBeNumber {
+                        operator: u.operator.token.clone(),
+                        span: u.operator.span,
+                    }),
+                    (other, _) => unreachable!("Parsed invalid unary operator {}", other),
+                }
+            }
+            Expression::Binary(b) => {
+                let left = self.evaluate(&b.left)?;
+                let right = self.evaluate(&b.right)?;
+                match (&b.operator.token, left, right) {
+                    (Token::EqualEqual, l, r) => Ok(Value::Bool(l == r)),
+                    (Token::BangEqual, l, r) => Ok(Value::Bool(l != r)),
+                    (Token::Plus, Value::String(l), Value::String(r)) => {
+                        Ok(Value::String(format!("{}{}", l, r).into()))
+                    }
+                    (Token::Plus, Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+                    (Token::Plus, _, _) => Err(RuntimeError::OperandsMustBeNumbersOrStrings {
+                        operator: b.operator.token.clone(),
+                        span: b.operator.span,
+                    }),
+                    (operator, Value::Number(l), Value::Number(r)) => match operator {
+                        Token::Minus => Ok(Value::Number(l - r)),
+                        Token::Star => Ok(Value::Number(l * r)),
+                        Token::Slash => Ok(Value::Number(l / r)),
+                        Token::Greater => Ok(Value::Bool(l > r)),
+                        Token::GreaterEqual => Ok(Value::Bool(l >= r)),
+                        Token::Less => Ok(Value::Bool(l < r)),
+                        Token::LessEqual => Ok(Value::Bool(l <= r)),
+                        other => unreachable!("Parsed invalid binary operator {}", other),
+                    },
+                    _ => Err(RuntimeError::OperandsMustBeNumbers {
+                        operator: b.operator.token.clone(),
+                        span: b.operator.span,
+                    }),
+                }
+            }
         }
     }
-}
 
-impl Interpretation<String> for Expression {
-    fn interpret(&self) -> String {
-        match self {
-            Expression::Binary(b) => match b.operator {
-                Token::Plus => {
-                    let lhs: String = b.left.interpret();
-                    let rhs: String = b.right.interpret();
-                    format!("{}{}", lhs, rhs)
-                }
-                _ => panic!("Unexpected operator in binary statement"),
-            },
-            Expression::LiteralStr(s) => String::from(s.value.clone()),
-            _ => panic!(&format!("{:?}", self)),
-        }
+    fn look_up_variable(&self, id: ExprId, name: &SpannedToken) -> Result<Value, RuntimeError> {
+        self.look_up(id, name.symbol(), name)
+    }
+
+    /// Looks up `name` where the resolver found it, reporting errors at `token`.
+    fn look_up(
+        &self,
+        id: ExprId,
+        name: Symbol,
+        token: &SpannedToken,
+    ) -> Result<Value, RuntimeError> {
+        let value = match self.locals.get(&id) {
+            Some(&distance) => self.environment.borrow().get_at(distance, name),
+            None => self.globals.borrow().get(name),
+        };
+        value.ok_or_else(|| RuntimeError::UndefinedVariable {
+            name: name.to_string(),
+            span: token.span,
+        })
     }
 }
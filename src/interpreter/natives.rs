@@ -0,0 +1,17 @@
+use super::{callable::NativeFunction, value::Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The native functions defined in every interpreter's global scope.
+pub const NATIVES: &[NativeFunction] = &[NativeFunction {
+    name: "clock",
+    arity: 0,
+    function: clock,
+}];
+
+/// Seconds since the Unix epoch, for timing scripts.
+fn clock(_: &[Value]) -> Value {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Value::Number(now.as_secs_f64())
+}
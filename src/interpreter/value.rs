@@ -0,0 +1,103 @@
+use super::{
+    callable::Callable,
+    class::{LoxClass, LoxInstance},
+};
+use crate::{lox::native::NativeInstance, symbol::Symbol};
+use std::{
+    cell::RefCell,
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+/// A dynamically typed Lox value produced by evaluating an expression.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(LoxString),
+    Callable(Rc<dyn Callable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    Native(NativeInstance),
+}
+
+impl Value {
+    /// Lox treats `nil` and `false` as falsey, and everything else as truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
+
+/// Values of different types are never equal, and callables, classes and instances are only equal
+/// to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Callable(a), Self::Callable(b)) => Rc::ptr_eq(a, b),
+            (Self::Class(a), Self::Class(b)) => Rc::ptr_eq(a, b),
+            (Self::Instance(a), Self::Instance(b)) => Rc::ptr_eq(a, b),
+            (Self::Native(a), Self::Native(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nil => write!(f, "nil"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Number(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "{}", s),
+            Self::Callable(c) => write!(f, "{}", c),
+            Self::Class(c) => write!(f, "{}", c),
+            Self::Instance(i) => write!(f, "{}", i.borrow()),
+            Self::Native(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// A string value. Literals keep the symbol they were interned as, so evaluating one neither
+/// allocates nor locks the interner, and literals compare as integers.
+#[derive(Debug, Clone)]
+pub enum LoxString {
+    Literal(Symbol),
+    /// A string built at runtime, such as by concatenation.
+    Built(Rc<str>),
+}
+
+impl LoxString {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Literal(symbol) => symbol.as_str(),
+            Self::Built(string) => string,
+        }
+    }
+}
+
+impl From<String> for LoxString {
+    fn from(string: String) -> Self {
+        Self::Built(string.into())
+    }
+}
+
+impl PartialEq for LoxString {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Literal(a), Self::Literal(b)) => a == b,
+            (Self::Built(a), Self::Built(b)) if Rc::ptr_eq(a, b) => true,
+            (a, b) => a.as_str() == b.as_str(),
+        }
+    }
+}
+
+impl Display for LoxString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
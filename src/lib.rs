@@ -0,0 +1,24 @@
+//! Lox, the language from *Crafting Interpreters*, with a tree-walking interpreter and a bytecode
+//! VM. Embed it through a [`Lox`] session.
+
+mod interpreter;
+mod lox;
+mod parser;
+mod resolver;
+mod scanner;
+mod symbol;
+mod vm;
+
+pub use interpreter::error::{HostError, RuntimeError};
+pub use lox::{
+    errors::LoxError,
+    host::{FromLox, HostCallable, HostMethod, IntoLox},
+    limits::{InterruptHandle, Limits},
+    native::{NativeClass, NativeInstance},
+    value::{Object, Value},
+    Lox, VmOptions,
+};
+pub use parser::errors::ParseError;
+pub use resolver::errors::ResolveError;
+pub use scanner::tokens::{Span, Token};
+pub use vm::errors::CompileError;
@@ -0,0 +1,30 @@
+use crate::{
+    interpreter::error::RuntimeError, parser::errors::ParseError, resolver::errors::ResolveError,
+    vm::errors::CompileError,
+};
+use std::fmt::Display;
+
+/// Everything that can go wrong running Lox source, by the stage that failed.
+#[derive(thiserror::Error, Debug)]
+pub enum LoxError {
+    /// The source couldn't be scanned or parsed. Scan errors are reported instead of parse
+    /// errors when there are any.
+    #[error("{}", lines(.0))]
+    Parse(Vec<ParseError>),
+    #[error("{}", lines(.0))]
+    Resolve(Vec<ResolveError>),
+    #[error("{}", lines(.0))]
+    Compile(Vec<CompileError>),
+    #[error("{0}")]
+    Runtime(#[from] RuntimeError),
+    #[error("Error: Can't pass an object to a different interpreter than the one it came from")]
+    ForeignObject,
+    #[error("Error: Only the bytecode VM can limit the size of its heap")]
+    UnsupportedHeapLimit,
+}
+
+/// Formats each error on a line of its own.
+fn lines<T: Display>(errors: &[T]) -> String {
+    let lines: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    lines.join("\n")
+}
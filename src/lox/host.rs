@@ -0,0 +1,307 @@
+use super::value::Value;
+use crate::interpreter::error::HostError;
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    marker::PhantomData,
+    rc::Rc,
+};
+
+/// A Rust type that Lox values can be passed to host functions as.
+pub trait FromLox: Sized {
+    /// Describes the values accepted, such as "a number", for error messages.
+    fn expected() -> String;
+    /// Converts `value`, or returns `None` if it isn't one of the values accepted.
+    fn from_lox(value: Value) -> Option<Self>;
+}
+
+/// A Rust type that host functions can return to Lox.
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+impl FromLox for Value {
+    fn expected() -> String {
+        "any value".to_string()
+    }
+
+    fn from_lox(value: Value) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl FromLox for f64 {
+    fn expected() -> String {
+        "a number".to_string()
+    }
+
+    fn from_lox(value: Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+impl FromLox for bool {
+    fn expected() -> String {
+        "a boolean".to_string()
+    }
+
+    fn from_lox(value: Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl FromLox for String {
+    fn expected() -> String {
+        "a string".to_string()
+    }
+
+    fn from_lox(value: Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// `nil` converts to `None`.
+impl<T: FromLox> FromLox for Option<T> {
+    fn expected() -> String {
+        format!("{} or nil", T::expected())
+    }
+
+    fn from_lox(value: Value) -> Option<Self> {
+        match value {
+            Value::Nil => Some(None),
+            value => T::from_lox(value).map(Some),
+        }
+    }
+}
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        self
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        Value::Nil
+    }
+}
+
+/// `None` converts to `nil`.
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        self.map_or(Value::Nil, IntoLox::into_lox)
+    }
+}
+
+/// The body of a [`HostFunction`], taking and returning values converted from the backend's own.
+pub(crate) type HostFn = dyn Fn(Vec<Value>) -> Result<Value, HostError>;
+
+/// A Rust closure registered as a Lox function, with its arguments and result converted.
+#[derive(Clone)]
+pub struct HostFunction {
+    pub(crate) name: Rc<str>,
+    pub(crate) arity: usize,
+    /// Whether the function takes any number of arguments beyond `arity`.
+    pub(crate) variadic: bool,
+    pub(crate) function: Rc<HostFn>,
+}
+
+impl HostFunction {
+    pub(crate) fn new<Args, F>(name: &str, function: F) -> Self
+    where
+        F: HostCallable<Args> + 'static,
+        F::Output: IntoLox,
+    {
+        Self {
+            name: name.into(),
+            arity: F::ARITY,
+            variadic: F::VARIADIC,
+            function: Rc::new(move |arguments| Ok(function.call_with(arguments)?.into_lox())),
+        }
+    }
+}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("variadic", &self.variadic)
+            .finish()
+    }
+}
+
+impl Display for HostFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+/// A Rust closure that Lox can call, such as `|x: f64, y: f64| x.hypot(y)`. Each parameter must
+/// be [`FromLox`]. A trailing `Vec<T>` parameter takes any number of further arguments.
+///
+/// `Args` only distinguishes the implementations for closures of different shapes.
+pub trait HostCallable<Args> {
+    type Output;
+    /// The number of arguments required.
+    const ARITY: usize;
+    /// Whether any number of arguments may follow the required ones.
+    const VARIADIC: bool;
+    /// Converts `arguments`, which the backend has checked the number of, and calls the closure.
+    fn call_with(&self, arguments: Vec<Value>) -> Result<Self::Output, HostError>;
+}
+
+/// Like [`HostCallable`], for closures taking the native instance they're called on first, such
+/// as `|player: &mut Player, amount: f64| player.health -= amount`.
+pub trait HostMethod<T, Args> {
+    type Output;
+    const ARITY: usize;
+    const VARIADIC: bool;
+    fn call_with(&self, receiver: &mut T, arguments: Vec<Value>)
+        -> Result<Self::Output, HostError>;
+}
+
+/// Marks a trailing `Vec<T>` parameter in [`HostCallable`]'s and [`HostMethod`]'s `Args`.
+pub struct Rest<T>(PhantomData<T>);
+
+/// Takes the next argument, which the backend has checked is there, and converts it.
+fn argument<T: FromLox>(
+    arguments: &mut impl Iterator<Item = (usize, Value)>,
+) -> Result<T, HostError> {
+    let (index, value) = arguments.next().expect("arity was already checked");
+    convert(index, value)
+}
+
+/// Converts the argument at zero-based `index`.
+fn convert<T: FromLox>(index: usize, value: Value) -> Result<T, HostError> {
+    T::from_lox(value).ok_or_else(|| HostError::InvalidArgument {
+        index: index + 1,
+        expected: T::expected(),
+    })
+}
+
+/// Converts whatever arguments are left, for a trailing `Vec<T>` parameter.
+fn rest<T: FromLox>(arguments: impl Iterator<Item = (usize, Value)>) -> Result<Vec<T>, HostError> {
+    arguments
+        .map(|(index, value)| convert(index, value))
+        .collect()
+}
+
+macro_rules! count {
+    () => { 0 };
+    ($head:ident $($tail:ident)*) => { 1 + count!($($tail)*) };
+}
+
+macro_rules! impl_host_callable {
+    ($($param:ident),*) => {
+        impl<F, R, $($param,)*> HostCallable<($($param,)*)> for F
+        where
+            F: Fn($($param),*) -> R,
+            $($param: FromLox,)*
+        {
+            type Output = R;
+            const ARITY: usize = count!($($param)*);
+            const VARIADIC: bool = false;
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call_with(&self, arguments: Vec<Value>) -> Result<R, HostError> {
+                let mut arguments = arguments.into_iter().enumerate();
+                $(let $param = argument(&mut arguments)?;)*
+                Ok(self($($param),*))
+            }
+        }
+
+        impl<F, R, V, $($param,)*> HostCallable<($($param,)* Rest<V>,)> for F
+        where
+            F: Fn($($param,)* Vec<V>) -> R,
+            V: FromLox,
+            $($param: FromLox,)*
+        {
+            type Output = R;
+            const ARITY: usize = count!($($param)*);
+            const VARIADIC: bool = true;
+
+            #[allow(non_snake_case, unused_mut)]
+            fn call_with(&self, arguments: Vec<Value>) -> Result<R, HostError> {
+                let mut arguments = arguments.into_iter().enumerate();
+                $(let $param = argument(&mut arguments)?;)*
+                Ok(self($($param,)* rest(arguments)?))
+            }
+        }
+
+        impl<F, R, T, $($param,)*> HostMethod<T, ($($param,)*)> for F
+        where
+            F: Fn(&mut T, $($param),*) -> R,
+            $($param: FromLox,)*
+        {
+            type Output = R;
+            const ARITY: usize = count!($($param)*);
+            const VARIADIC: bool = false;
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call_with(&self, receiver: &mut T, arguments: Vec<Value>) -> Result<R, HostError> {
+                let mut arguments = arguments.into_iter().enumerate();
+                $(let $param = argument(&mut arguments)?;)*
+                Ok(self(receiver, $($param),*))
+            }
+        }
+
+        impl<F, R, T, V, $($param,)*> HostMethod<T, ($($param,)* Rest<V>,)> for F
+        where
+            F: Fn(&mut T, $($param,)* Vec<V>) -> R,
+            V: FromLox,
+            $($param: FromLox,)*
+        {
+            type Output = R;
+            const ARITY: usize = count!($($param)*);
+            const VARIADIC: bool = true;
+
+            #[allow(non_snake_case, unused_mut)]
+            fn call_with(&self, receiver: &mut T, arguments: Vec<Value>) -> Result<R, HostError> {
+                let mut arguments = arguments.into_iter().enumerate();
+                $(let $param = argument(&mut arguments)?;)*
+                Ok(self(receiver, $($param,)* rest(arguments)?))
+            }
+        }
+    };
+}
+
+impl_host_callable!();
+impl_host_callable!(A);
+impl_host_callable!(A, B);
+impl_host_callable!(A, B, C);
+impl_host_callable!(A, B, C, D);
+impl_host_callable!(A, B, C, D, E);
+impl_host_callable!(A, B, C, D, E, G);
@@ -0,0 +1,45 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Bounds on what a single run may do, for scripts that can't be trusted to finish.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Steps each run may take: statements on the tree-walking interpreter, or instructions on the
+    /// VM. `None` for no limit.
+    pub steps: Option<u64>,
+    /// Calls that may be in progress at once. A call beyond this is a stack overflow. `None` for
+    /// the backend's default, [`Limits::TREE_WALK_CALL_DEPTH`] or [`Limits::VM_CALL_DEPTH`].
+    pub call_depth: Option<usize>,
+    /// Bytes the VM's heap, value stack and call frames may hold once garbage is collected. The
+    /// tree-walking interpreter has no heap of its own to measure, so it refuses limits that set
+    /// this.
+    pub heap_bytes: Option<usize>,
+}
+
+impl Limits {
+    /// How deeply calls may nest on the tree-walking interpreter by default. It recurses on the
+    /// Rust stack, growing it onto the heap as needed, and each call can take tens of kilobytes in
+    /// a debug build, so this is kept far lower than on the VM.
+    pub const TREE_WALK_CALL_DEPTH: usize = 1000;
+    /// How deeply calls may nest on the VM by default. Its frames live on the heap, so this only
+    /// stops runaway recursion.
+    pub const VM_CALL_DEPTH: usize = 100_000;
+}
+
+/// Stops a running script from another thread, which then fails with an interrupted error at its
+/// next step. An interrupt sent while nothing is running stops the next run instead.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether an interrupt is pending, clearing it.
+    pub(crate) fn take(&self) -> bool {
+        self.0.load(Ordering::Relaxed) && self.0.swap(false, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,197 @@
+use self::{
+    errors::LoxError,
+    host::{HostCallable, HostFunction, IntoLox},
+    limits::{InterruptHandle, Limits},
+    native::NativeClass,
+    value::Value,
+};
+use crate::{
+    interpreter::{self, Interpreter},
+    parser, resolver,
+    scanner::Scanner,
+    symbol::Symbol,
+    vm::Vm,
+};
+use std::rc::Rc;
+
+pub mod errors;
+pub mod host;
+pub mod limits;
+pub mod native;
+pub mod value;
+
+/// Which implementation executes programs.
+enum Backend {
+    TreeWalk(Interpreter),
+    Vm(Box<Vm>),
+}
+
+/// An interpreter session for embedding Lox. Globals persist from one call to the next.
+pub struct Lox {
+    backend: Backend,
+}
+
+/// Switches for debugging the bytecode VM, which print to standard output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VmOptions {
+    /// Print the bytecode of every program compiled.
+    pub disassemble: bool,
+    /// Print the stack and each instruction as it executes.
+    pub trace: bool,
+    /// Collect garbage before every allocation, to flush out objects that aren't rooted.
+    pub stress_gc: bool,
+    /// Print garbage collector statistics after each run.
+    pub gc_stats: bool,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self {
+            backend: Backend::TreeWalk(Interpreter::new()),
+        }
+    }
+}
+
+impl Lox {
+    /// A session running programs on the tree-walking interpreter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A session running programs on the bytecode VM.
+    pub fn new_vm() -> Self {
+        Self::with_vm_options(VmOptions::default())
+    }
+
+    /// A session running programs on the bytecode VM with debugging switches set.
+    pub fn with_vm_options(options: VmOptions) -> Self {
+        let mut vm = Vm::new();
+        vm.set_disassemble(options.disassemble);
+        vm.set_trace(options.trace);
+        vm.set_stress_gc(options.stress_gc);
+        vm.set_gc_stats(options.gc_stats);
+        Self {
+            backend: Backend::Vm(Box::new(vm)),
+        }
+    }
+
+    /// Bounds every run from now on. Exceeding a limit is a runtime error, after which the
+    /// session is still usable. Fails, leaving the limits as they were, if the backend can't
+    /// enforce one of them.
+    pub fn set_limits(&mut self, limits: Limits) -> Result<(), LoxError> {
+        match &mut self.backend {
+            Backend::TreeWalk(_) if limits.heap_bytes.is_some() => {
+                return Err(LoxError::UnsupportedHeapLimit)
+            }
+            Backend::TreeWalk(interpreter) => interpreter.set_limits(limits),
+            Backend::Vm(vm) => vm.set_limits(limits),
+        }
+        Ok(())
+    }
+
+    /// A handle another thread can use to stop whatever this session is running.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        match &self.backend {
+            Backend::TreeWalk(interpreter) => interpreter.interrupt_handle(),
+            Backend::Vm(vm) => vm.interrupt_handle(),
+        }
+    }
+
+    /// Scans, parses, resolves and runs a program.
+    pub fn run_source(&mut self, source: &str) -> Result<(), LoxError> {
+        let statements = parser::parse(Scanner::new(source)).map_err(LoxError::Parse)?;
+        let locals = resolver::resolve(&statements).map_err(LoxError::Resolve)?;
+        match &mut self.backend {
+            Backend::TreeWalk(interpreter) => interpreter.interpret(&statements, locals)?,
+            Backend::Vm(vm) => {
+                let script = vm.compile(&statements).map_err(LoxError::Compile)?;
+                vm.run(script)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates a single expression, such as `a + b` or `f(1)`, and returns its value.
+    pub fn eval_expression(&mut self, source: &str) -> Result<Value, LoxError> {
+        let expression = parser::parse_expression(Scanner::new(source)).map_err(LoxError::Parse)?;
+        let locals = resolver::resolve_expression(&expression).map_err(LoxError::Resolve)?;
+        match &mut self.backend {
+            Backend::TreeWalk(interpreter) => {
+                let value = interpreter.evaluate_expression(&expression, locals)?;
+                Ok(interpreter.export(value))
+            }
+            Backend::Vm(vm) => {
+                let script = vm
+                    .compile_expression(&expression)
+                    .map_err(LoxError::Compile)?;
+                let value = vm.run(script)?;
+                Ok(vm.export(value))
+            }
+        }
+    }
+
+    /// The value of a global variable, or `None` if it isn't defined.
+    pub fn get_global(&mut self, name: &str) -> Option<Value> {
+        match &mut self.backend {
+            Backend::TreeWalk(interpreter) => {
+                let value = interpreter.get_global(Symbol::get(name)?)?;
+                Some(interpreter.export(value))
+            }
+            Backend::Vm(vm) => {
+                let value = vm.get_global(name)?;
+                Some(vm.export(value))
+            }
+        }
+    }
+
+    /// Defines a global variable, or overwrites it if it already exists. Global names, like every
+    /// identifier and string literal in a program, are interned for the rest of the process, so
+    /// hosts shouldn't make up an unbounded number of distinct names.
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) -> Result<(), LoxError> {
+        match &mut self.backend {
+            Backend::TreeWalk(interpreter) => {
+                let value = interpreter
+                    .import(value.into())
+                    .map_err(|_| LoxError::ForeignObject)?;
+                interpreter.set_global(Symbol::intern(name), value);
+            }
+            Backend::Vm(vm) => {
+                let value = vm
+                    .import(value.into())
+                    .map_err(|_| LoxError::ForeignObject)?;
+                vm.set_global(name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Defines a global function implemented by a Rust closure, such as
+    /// `|x: f64, y: f64| x.hypot(y)`. Arguments that can't be converted to the closure's
+    /// parameter types are runtime errors at the call.
+    pub fn register_function<Args, F>(&mut self, name: &str, function: F)
+    where
+        F: HostCallable<Args> + 'static,
+        F::Output: IntoLox,
+    {
+        let function = HostFunction::new(name, function);
+        match &mut self.backend {
+            Backend::TreeWalk(interpreter) => interpreter.set_global(
+                Symbol::intern(name),
+                interpreter::value::Value::Callable(Rc::new(function)),
+            ),
+            Backend::Vm(vm) => vm.define_host_function(function),
+        }
+    }
+
+    /// Defines a native class as a global, named as it was declared.
+    pub fn register_class<T: 'static>(&mut self, class: &NativeClass<T>) {
+        let class = class.definition().clone();
+        match &mut self.backend {
+            Backend::TreeWalk(interpreter) => interpreter.set_global(
+                Symbol::intern(class.name()),
+                interpreter::value::Value::Callable(class),
+            ),
+            Backend::Vm(vm) => vm.define_native_class(class),
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use super::{
+    host::{FromLox, HostCallable, HostMethod, IntoLox},
+    value::Value,
+};
+use crate::interpreter::error::HostError;
+use ahash::AHashMap;
+use std::{
+    any::Any,
+    cell::RefCell,
+    fmt::{self, Debug, Display, Formatter},
+    marker::PhantomData,
+    rc::Rc,
+};
+
+/// A Rust type exposed to Lox as a class. Scripts construct instances by calling the class, and
+/// read and assign their fields and call their methods with `.`, as with any other instance.
+pub struct NativeClass<T> {
+    definition: Rc<ClassDefinition>,
+    marker: PhantomData<T>,
+}
+
+impl<T> Clone for NativeClass<T> {
+    fn clone(&self) -> Self {
+        Self {
+            definition: self.definition.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> NativeClass<T> {
+    /// A class with no fields or methods, which scripts can't construct.
+    pub fn new(name: &str) -> Self {
+        Self {
+            definition: Rc::new(ClassDefinition {
+                name: name.into(),
+                constructor: None,
+                fields: AHashMap::new(),
+                methods: AHashMap::new(),
+            }),
+            marker: PhantomData,
+        }
+    }
+
+    /// Lets scripts construct instances by calling the class with `constructor`'s arguments.
+    pub fn constructor<Args, F>(mut self, constructor: F) -> Self
+    where
+        F: HostCallable<Args, Output = T> + 'static,
+    {
+        self.definition_mut().constructor = Some(Constructor {
+            arity: F::ARITY,
+            variadic: F::VARIADIC,
+            function: Rc::new(move |arguments| {
+                let data: Rc<dyn Any> = Rc::new(RefCell::new(constructor.call_with(arguments)?));
+                Ok(data)
+            }),
+        });
+        self
+    }
+
+    /// A field scripts can read with `get` and assign with `set`.
+    pub fn field<R, V>(
+        mut self,
+        name: &str,
+        get: impl Fn(&T) -> R + 'static,
+        set: impl Fn(&mut T, V) + 'static,
+    ) -> Self
+    where
+        R: IntoLox,
+        V: FromLox,
+    {
+        let field = Field {
+            get: getter(get),
+            set: Some(Rc::new(move |data, value| {
+                let value = V::from_lox(value).ok_or_else(|| HostError::InvalidValue {
+                    expected: V::expected(),
+                })?;
+                set(&mut data_of::<T>(data).borrow_mut(), value);
+                Ok(())
+            })),
+        };
+        self.definition_mut().fields.insert(name.to_string(), field);
+        self
+    }
+
+    /// A field scripts can read with `get` but not assign.
+    pub fn read_only<R: IntoLox>(mut self, name: &str, get: impl Fn(&T) -> R + 'static) -> Self {
+        let field = Field {
+            get: getter(get),
+            set: None,
+        };
+        self.definition_mut().fields.insert(name.to_string(), field);
+        self
+    }
+
+    /// A method, which gets the instance it's called on before its arguments.
+    pub fn method<Args, F>(mut self, name: &str, method: F) -> Self
+    where
+        F: HostMethod<T, Args> + 'static,
+        F::Output: IntoLox,
+    {
+        let method = Method {
+            arity: F::ARITY,
+            variadic: F::VARIADIC,
+            function: Rc::new(move |data, arguments| {
+                let result = method.call_with(&mut data_of::<T>(data).borrow_mut(), arguments)?;
+                Ok(result.into_lox())
+            }),
+        };
+        self.definition_mut()
+            .methods
+            .insert(name.to_string(), method);
+        self
+    }
+
+    /// Wraps `value` as a new instance of the class.
+    pub fn instance(&self, value: T) -> Value {
+        self.wrap(Rc::new(RefCell::new(value)))
+    }
+
+    /// Wraps a value the host keeps sharing as an instance of the class, so the host sees
+    /// whatever scripts do to it.
+    pub fn wrap(&self, data: Rc<RefCell<T>>) -> Value {
+        Value::Native(NativeInstance {
+            class: self.definition.clone(),
+            data,
+        })
+    }
+
+    pub(crate) fn definition(&self) -> &Rc<ClassDefinition> {
+        &self.definition
+    }
+
+    fn definition_mut(&mut self) -> &mut ClassDefinition {
+        Rc::get_mut(&mut self.definition).expect("A native class can't change once it's shared")
+    }
+}
+
+fn getter<T: 'static, R: IntoLox>(get: impl Fn(&T) -> R + 'static) -> Rc<Getter> {
+    Rc::new(move |data| get(&data_of::<T>(data).borrow()).into_lox())
+}
+
+/// The `RefCell<T>` behind an instance, which only its own class's closures are given.
+fn data_of<T: 'static>(data: &dyn Any) -> &RefCell<T> {
+    data.downcast_ref()
+        .expect("Native instance data doesn't match its class")
+}
+
+type Getter = dyn Fn(&dyn Any) -> Value;
+type Setter = dyn Fn(&dyn Any, Value) -> Result<(), HostError>;
+type MethodFn = dyn Fn(&dyn Any, Vec<Value>) -> Result<Value, HostError>;
+type ConstructorFn = dyn Fn(Vec<Value>) -> Result<Rc<dyn Any>, HostError>;
+
+/// A [`NativeClass`] with its type erased, which both backends use directly.
+pub struct ClassDefinition {
+    name: Rc<str>,
+    constructor: Option<Constructor>,
+    fields: AHashMap<String, Field>,
+    methods: AHashMap<String, Method>,
+}
+
+struct Constructor {
+    arity: usize,
+    variadic: bool,
+    function: Rc<ConstructorFn>,
+}
+
+struct Field {
+    get: Rc<Getter>,
+    /// `None` for a read-only field.
+    set: Option<Rc<Setter>>,
+}
+
+#[derive(Clone)]
+struct Method {
+    arity: usize,
+    variadic: bool,
+    function: Rc<MethodFn>,
+}
+
+impl ClassDefinition {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The number of arguments the constructor takes, or zero if there isn't one.
+    pub fn arity(&self) -> usize {
+        self.constructor.as_ref().map_or(0, |c| c.arity)
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        self.constructor.as_ref().is_some_and(|c| c.variadic)
+    }
+
+    /// Constructs an instance from arguments the backend has checked the number of.
+    pub fn construct(self: &Rc<Self>, arguments: Vec<Value>) -> Result<Value, HostError> {
+        let constructor = self.constructor.as_ref().ok_or(HostError::NoConstructor)?;
+        Ok(Value::Native(NativeInstance {
+            class: self.clone(),
+            data: (constructor.function)(arguments)?,
+        }))
+    }
+}
+
+impl Debug for ClassDefinition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClassDefinition")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl Display for ClassDefinition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// An instance of a [`NativeClass`]. Copies of it share the Rust value.
+#[derive(Clone)]
+pub struct NativeInstance {
+    class: Rc<ClassDefinition>,
+    /// Always a `RefCell` of the class's Rust type.
+    data: Rc<dyn Any>,
+}
+
+/// What a property of a [`NativeInstance`] evaluates to.
+pub enum Property {
+    Field(Value),
+    Method(BoundMethod),
+}
+
+impl NativeInstance {
+    pub fn class(&self) -> &Rc<ClassDefinition> {
+        &self.class
+    }
+
+    /// The Rust value behind the instance, if it's a `T`.
+    pub fn downcast<T: 'static>(&self) -> Option<Rc<RefCell<T>>> {
+        self.data.clone().downcast().ok()
+    }
+
+    /// Reads a field, or binds a method to the instance. Fields shadow methods.
+    pub fn get(&self, name: &str) -> Option<Property> {
+        if let Some(field) = self.class.fields.get(name) {
+            return Some(Property::Field((field.get)(&*self.data)));
+        }
+        let method = self.class.methods.get(name)?;
+        Some(Property::Method(BoundMethod {
+            name: name.into(),
+            receiver: self.clone(),
+            method: method.clone(),
+        }))
+    }
+
+    pub fn set(&self, name: &str, value: Value) -> Result<(), HostError> {
+        let field = self
+            .class
+            .fields
+            .get(name)
+            .ok_or(HostError::UndefinedProperty)?;
+        let set = field.set.as_ref().ok_or(HostError::ReadOnlyProperty)?;
+        set(&*self.data, value)
+    }
+}
+
+/// Instances are only equal to the instances sharing their Rust value.
+impl PartialEq for NativeInstance {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::as_ptr(&self.data) as *const () == Rc::as_ptr(&other.data) as *const ()
+    }
+}
+
+impl Debug for NativeInstance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeInstance")
+            .field("class", &self.class.name)
+            .finish()
+    }
+}
+
+impl Display for NativeInstance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+/// A native method accessed from an instance, remembering the instance to call it on.
+#[derive(Clone)]
+pub struct BoundMethod {
+    name: Rc<str>,
+    receiver: NativeInstance,
+    method: Method,
+}
+
+impl BoundMethod {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.method.arity
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        self.method.variadic
+    }
+
+    /// Calls the method with arguments the backend has checked the number of.
+    pub fn call(&self, arguments: Vec<Value>) -> Result<Value, HostError> {
+        (self.method.function)(&*self.receiver.data, arguments)
+    }
+}
+
+impl Debug for BoundMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoundMethod")
+            .field("name", &self.name)
+            .field("receiver", &self.receiver)
+            .finish()
+    }
+}
+
+impl Display for BoundMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
@@ -0,0 +1,105 @@
+use super::native::NativeInstance;
+use crate::{interpreter, vm::pin::Pin};
+use std::{
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A Lox value passed between the host and scripts, whichever backend runs them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    /// A function, class or instance, which only the backend it came from can take back.
+    Object(Object),
+    /// An instance of a [`crate::NativeClass`], which any session can take.
+    Native(NativeInstance),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nil => write!(f, "nil"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Number(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "{}", s),
+            Self::Object(o) => write!(f, "{}", o),
+            Self::Native(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+/// A handle to an object living in a backend.
+#[derive(Debug, Clone)]
+pub struct Object {
+    /// Identifies the backend the object belongs to.
+    pub(crate) owner: usize,
+    pub(crate) kind: ObjectKind,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum ObjectKind {
+    TreeWalk(interpreter::value::Value),
+    /// Objects on the VM's heap stay pinned until the last handle to them is dropped. The
+    /// description is taken when the object is handed out, so printing doesn't need the heap.
+    Vm {
+        pin: Rc<Pin>,
+        description: Rc<str>,
+    },
+}
+
+/// Objects are only equal to themselves.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        self.owner == other.owner
+            && match (&self.kind, &other.kind) {
+                (ObjectKind::TreeWalk(a), ObjectKind::TreeWalk(b)) => a == b,
+                (ObjectKind::Vm { pin: a, .. }, ObjectKind::Vm { pin: b, .. }) => {
+                    a.reference() == b.reference()
+                }
+                _ => false,
+            }
+    }
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ObjectKind::TreeWalk(value) => write!(f, "{}", value),
+            ObjectKind::Vm { description, .. } => write!(f, "{}", description),
+        }
+    }
+}
+
+/// Identifies a new backend, so objects can't be passed to one they didn't come from.
+pub(crate) fn next_owner() -> usize {
+    static NEXT_OWNER: AtomicUsize = AtomicUsize::new(0);
+    NEXT_OWNER.fetch_add(1, Ordering::Relaxed)
+}
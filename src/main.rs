@@ -1,23 +1,14 @@
-use interpreter::Interpretation;
+use loxide::{Lox, VmOptions};
 use std::error::Error;
 use std::io::{self, prelude::*};
 use std::path::Path;
 
-mod interpreter;
-mod parser;
-mod scanner;
-
 fn main() {
-    let mut args = std::env::args();
-    match {
-        if args.len() > 2 {
-            Err(UsageError::TooManyArgs.into())
-        } else if let Some(arg) = args.nth(1) {
-            run_file(Path::new(&arg))
-        } else {
-            run_prompt()
-        }
-    } {
+    let result = parse_args().and_then(|(lox, script)| match script {
+        Some(script) => run_file(Path::new(&script), lox),
+        None => run_prompt(lox),
+    });
+    match result {
         Ok(_) => println!("Goodbye!"),
         Err(error) => println!("{}", error),
     }
@@ -25,26 +16,62 @@ fn main() {
 
 #[derive(thiserror::Error, Debug)]
 pub enum UsageError {
-    #[error("Usage: loxide [script]")]
+    #[error("{USAGE}")]
     TooManyArgs,
+    #[error("Unknown option '{0}'\n{USAGE}")]
+    UnknownOption(String),
+}
+
+const USAGE: &str =
+    "Usage: loxide [--vm] [--disassemble] [--trace] [--stress-gc] [--gc-stats] [script]";
+
+/// Parses the command line into a session on the backend to use and an optional script path.
+fn parse_args() -> Result<(Lox, Option<String>), Box<dyn Error>> {
+    let mut use_vm = false;
+    let mut options = VmOptions::default();
+    let mut script = None;
+    for arg in std::env::args().skip(1) {
+        // Bytecode and garbage collection only exist on the VM, so those flags select it too.
+        if arg == "--vm" {
+            use_vm = true;
+        } else if arg == "--disassemble" {
+            (use_vm, options.disassemble) = (true, true);
+        } else if arg == "--trace" {
+            (use_vm, options.trace) = (true, true);
+        } else if arg == "--stress-gc" {
+            (use_vm, options.stress_gc) = (true, true);
+        } else if arg == "--gc-stats" {
+            (use_vm, options.gc_stats) = (true, true);
+        } else if arg.starts_with("--") {
+            return Err(UsageError::UnknownOption(arg).into());
+        } else if script.is_some() {
+            return Err(UsageError::TooManyArgs.into());
+        } else {
+            script = Some(arg);
+        }
+    }
+    let lox = if use_vm {
+        Lox::with_vm_options(options)
+    } else {
+        Lox::new()
+    };
+    Ok((lox, script))
 }
 
-fn run_file(path: &Path) -> Result<(), Box<dyn Error>> {
+fn run_file(path: &Path, mut lox: Lox) -> Result<(), Box<dyn Error>> {
     let source = std::fs::read_to_string(path)?;
-    run(source)
+    run(&source, &mut lox);
+    Ok(())
 }
 
-fn run_prompt() -> Result<(), Box<dyn Error>> {
+fn run_prompt(mut lox: Lox) -> Result<(), Box<dyn Error>> {
     let stdin = io::stdin();
     let lines = stdin.lock().lines();
     println!("loxide");
     print!("> ");
     io::stdout().flush()?;
     for line in lines {
-        let buffer = line?;
-        if let Err(error) = run(buffer) {
-            println!("Error: {}", error);
-        }
+        run(&line?, &mut lox);
         print!("> ");
         io::stdout().flush()?;
     }
@@ -52,26 +79,9 @@ fn run_prompt() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn run(source: String) -> Result<(), Box<dyn Error>> {
-    match scanner::scan(&source) {
-        Ok(mut tokens) => {
-            for token in tokens.iter() {
-                print!("{}", token);
-            }
-            println!();
-            match parser::parse(&mut tokens) {
-                Ok(expr) => {
-                    let val: String = expr.interpret();
-                    dbg!(val);
-                }
-                Err(e) => panic!("failed to parse"),
-            }
-        }
-        Err(scan_errors) => {
-            for error in scan_errors.iter() {
-                println!("{}", error);
-            }
-        }
+/// Runs source, printing any errors. Errors in a script don't stop the CLI.
+fn run(source: &str, lox: &mut Lox) {
+    if let Err(error) = lox.run_source(source) {
+        println!("{}", error);
     }
-    Ok(())
 }
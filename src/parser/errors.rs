@@ -1,9 +1,32 @@
+use crate::scanner::{
+    errors::ScanError,
+    tokens::{Span, Token},
+};
+
 #[derive(thiserror::Error, Debug)]
 pub enum ParseError {
+    #[error("{0}")]
+    Scan(#[from] ScanError),
     #[error("Error: failed to pop token off the stack")]
     PopFailed,
-    #[error("Error: Missing right parenthesis")]
-    MissingRightParen,
-    #[error("Error: expected expression")]
-    ExpectedExpression,
+    #[error("Error: Missing right parenthesis at {span}, found '{found}'")]
+    MissingRightParen { found: Token, span: Span },
+    #[error("Error: Expected {expected} at {span}, found '{found}'")]
+    UnexpectedToken {
+        expected: String,
+        found: Token,
+        span: Span,
+    },
+    #[error("Error: Can't have more than 255 arguments at {span}")]
+    TooManyArguments { span: Span },
+    #[error("Error: Can't have more than 255 parameters at {span}")]
+    TooManyParameters { span: Span },
+    #[error("Error: Invalid assignment target at {span}")]
+    InvalidAssignmentTarget { span: Span },
+    #[error("Error: Expected expression at {span}, found '{found}'")]
+    ExpectedExpression { found: Token, span: Span },
+    #[error("Error: Expected '}}' to close interpolated expression at {span}, found '{found}'")]
+    UnterminatedInterpolation { found: Token, span: Span },
+    #[error("Error: Too deeply nested at {span}")]
+    TooDeeplyNested { span: Span },
 }
@@ -1,17 +1,44 @@
-use crate::scanner::tokens::Token;
-use std::fmt::{self, Debug, Display, Formatter};
+use crate::{scanner::tokens::SpannedToken, symbol::Symbol};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Uniquely identifies an expression that refers to a variable, so the resolver can record which
+/// scope the variable lives in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(usize);
+impl ExprId {
+    fn next() -> ExprId {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        ExprId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum Expression {
-    LiteralStr(Literal<String>),
+    LiteralNil,
+    LiteralStr(Literal<Symbol>),
     LiteralNum(Literal<f64>),
     LiteralBool(Literal<bool>),
     Unary(Unary),
     Binary(Binary),
     Grouping(Grouping),
+    Variable(Variable),
+    Assign(Assign),
+    Logical(Logical),
+    Call(Call),
+    Get(Get),
+    Set(Set),
+    This(This),
+    Super(Super),
+    Interpolation(Interpolation),
 }
 impl Expression {
-    pub fn literal_str(value: String) -> Expression {
+    pub fn literal_nil() -> Expression {
+        Expression::LiteralNil
+    }
+    pub fn literal_str(value: Symbol) -> Expression {
         Expression::LiteralStr(Literal { value })
     }
     pub fn literal_num(value: f64) -> Expression {
@@ -20,13 +47,13 @@ impl Expression {
     pub fn literal_bool(value: bool) -> Expression {
         Expression::LiteralBool(Literal { value })
     }
-    pub fn unary(operator: Token, right: Expression) -> Expression {
+    pub fn unary(operator: SpannedToken, right: Expression) -> Expression {
         Expression::Unary(Unary {
             operator,
             right: Box::new(right),
         })
     }
-    pub fn binary(left: Expression, operator: Token, right: Expression) -> Expression {
+    pub fn binary(left: Expression, operator: SpannedToken, right: Expression) -> Expression {
         Expression::Binary(Binary {
             left: Box::new(left),
             operator,
@@ -38,16 +65,79 @@ impl Expression {
             expression: Box::new(expression),
         })
     }
+    pub fn variable(name: SpannedToken) -> Expression {
+        Expression::Variable(Variable::new(name))
+    }
+    pub fn logical(left: Expression, operator: SpannedToken, right: Expression) -> Expression {
+        Expression::Logical(Logical {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        })
+    }
+    pub fn call(callee: Expression, paren: SpannedToken, arguments: Vec<Expression>) -> Expression {
+        Expression::Call(Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
+    }
+    pub fn get(object: Expression, name: SpannedToken) -> Expression {
+        Expression::Get(Get {
+            object: Box::new(object),
+            name,
+        })
+    }
+    pub fn set(object: Expression, name: SpannedToken, value: Expression) -> Expression {
+        Expression::Set(Set {
+            object: Box::new(object),
+            name,
+            value: Box::new(value),
+        })
+    }
+    pub fn this(keyword: SpannedToken) -> Expression {
+        Expression::This(This {
+            id: ExprId::next(),
+            keyword,
+        })
+    }
+    pub fn super_method(keyword: SpannedToken, method: SpannedToken) -> Expression {
+        Expression::Super(Super {
+            id: ExprId::next(),
+            keyword,
+            method,
+        })
+    }
+    pub fn interpolation(parts: Vec<Expression>) -> Expression {
+        Expression::Interpolation(Interpolation { parts })
+    }
+    pub fn assign(name: SpannedToken, value: Expression) -> Expression {
+        Expression::Assign(Assign {
+            id: ExprId::next(),
+            name,
+            value: Box::new(value),
+        })
+    }
 }
 impl Display for Expression {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match &self {
-            &Self::LiteralStr(s) => Display::fmt(&s, f),
-            &Self::LiteralNum(s) => Display::fmt(&s, f),
-            &Self::LiteralBool(s) => Display::fmt(&s, f),
-            &Self::Unary(s) => Display::fmt(&s, f),
-            &Self::Binary(s) => Display::fmt(&s, f),
-            &Self::Grouping(s) => Display::fmt(&s, f),
+        match self {
+            Self::LiteralNil => write!(f, "nil"),
+            Self::LiteralStr(s) => Display::fmt(s, f),
+            Self::LiteralNum(s) => Display::fmt(s, f),
+            Self::LiteralBool(s) => Display::fmt(s, f),
+            Self::Unary(s) => Display::fmt(s, f),
+            Self::Binary(s) => Display::fmt(s, f),
+            Self::Grouping(s) => Display::fmt(s, f),
+            Self::Variable(s) => Display::fmt(s, f),
+            Self::Assign(s) => Display::fmt(s, f),
+            Self::Logical(s) => Display::fmt(s, f),
+            Self::Call(s) => Display::fmt(s, f),
+            Self::Get(s) => Display::fmt(s, f),
+            Self::Set(s) => Display::fmt(s, f),
+            Self::This(s) => Display::fmt(s, f),
+            Self::Super(s) => Display::fmt(s, f),
+            Self::Interpolation(s) => Display::fmt(s, f),
         }
     }
 }
@@ -55,7 +145,7 @@ impl Display for Expression {
 #[derive(Debug, Clone)]
 pub struct Binary {
     pub left: Box<Expression>,
-    pub operator: Token,
+    pub operator: SpannedToken,
     pub right: Box<Expression>,
 }
 impl Display for Binary {
@@ -86,7 +176,7 @@ impl<T: Display + Debug> Display for Literal<T> {
 
 #[derive(Debug, Clone)]
 pub struct Unary {
-    pub operator: Token,
+    pub operator: SpannedToken,
     pub right: Box<Expression>,
 }
 impl Display for Unary {
@@ -94,3 +184,128 @@ impl Display for Unary {
         write!(f, "({}{})", self.operator, self.right)
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub id: ExprId,
+    pub name: SpannedToken,
+}
+impl Variable {
+    pub fn new(name: SpannedToken) -> Variable {
+        Variable {
+            id: ExprId::next(),
+            name,
+        }
+    }
+}
+impl Display for Variable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Assign {
+    pub id: ExprId,
+    pub name: SpannedToken,
+    pub value: Box<Expression>,
+}
+impl Display for Assign {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(= {} {})", self.name, self.value)
+    }
+}
+
+/// A short-circuiting `and` or `or` expression.
+#[derive(Debug, Clone)]
+pub struct Logical {
+    pub left: Box<Expression>,
+    pub operator: SpannedToken,
+    pub right: Box<Expression>,
+}
+impl Display for Logical {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "({} {} {})", self.operator, self.left, self.right)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub callee: Box<Expression>,
+    /// The closing parenthesis, used to locate errors raised by the call.
+    pub paren: SpannedToken,
+    pub arguments: Vec<Expression>,
+}
+impl Display for Call {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(call {}", self.callee)?;
+        for argument in &self.arguments {
+            write!(f, " {}", argument)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A property access, `object.name`.
+#[derive(Debug, Clone)]
+pub struct Get {
+    pub object: Box<Expression>,
+    pub name: SpannedToken,
+}
+impl Display for Get {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(. {} {})", self.object, self.name)
+    }
+}
+
+/// A property assignment, `object.name = value`.
+#[derive(Debug, Clone)]
+pub struct Set {
+    pub object: Box<Expression>,
+    pub name: SpannedToken,
+    pub value: Box<Expression>,
+}
+impl Display for Set {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(= (. {} {}) {})", self.object, self.name, self.value)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct This {
+    pub id: ExprId,
+    pub keyword: SpannedToken,
+}
+impl Display for This {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "this")
+    }
+}
+
+/// A superclass method access, `super.method`.
+#[derive(Debug, Clone)]
+pub struct Super {
+    pub id: ExprId,
+    pub keyword: SpannedToken,
+    pub method: SpannedToken,
+}
+impl Display for Super {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(super {})", self.method)
+    }
+}
+
+/// An interpolated string, `"a ${b} c"`, whose parts are stringified and concatenated.
+#[derive(Debug, Clone)]
+pub struct Interpolation {
+    pub parts: Vec<Expression>,
+}
+impl Display for Interpolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(str")?;
+        for part in &self.parts {
+            write!(f, " {}", part)?;
+        }
+        write!(f, ")")
+    }
+}
@@ -1,159 +1,722 @@
-use self::{errors::ParseError, expressions::Expression};
-use crate::scanner::tokens::Token::{self, *};
+use self::{
+    errors::ParseError,
+    expressions::{Expression, Variable},
+    statements::{Function, Stmt},
+};
+use crate::scanner::{
+    errors::ScanError,
+    tokens::{
+        SpannedToken,
+        Token::{self, *},
+    },
+    Scanner,
+};
+use std::rc::Rc;
 
 pub mod errors;
 pub mod expressions;
+pub mod statements;
 
-pub fn parse(tokens: &mut Vec<Token>) -> Result<Expression, ParseError> {
-    tokens.reverse(); // Reverse so we can pop() off the "front" of the vec
+/// Maximum number of arguments in a call, or parameters in a function declaration.
+const MAX_ARGUMENTS: usize = 255;
+
+/// How deeply statements and expressions may nest, counting each link of a chain such as
+/// `a + b + c` or `a.b()` as a level. The parser and every later pass recurse over the tree, and
+/// at this depth they all stay within a main thread's stack even in a debug build, whatever the
+/// input.
+const MAX_NESTING: usize = 256;
+
+/// Parses a whole program, pulling tokens from `scanner` as they're needed. After an error the
+/// parser synchronizes to the next statement and carries on, so every error in the source is
+/// reported at once. Scan errors take precedence, as the parse errors they cause are only noise.
+pub fn parse(scanner: Scanner) -> Result<Vec<Stmt>, Vec<ParseError>> {
+    let mut tokens = Tokens::new(scanner);
     let mut errors = Vec::new();
-    let mut expression_list = Vec::new();
-    expression_list.push(expression(tokens, &mut errors).map_err(|expr| {
-        synchronize(tokens);
-        expr
-    }));
-    for error in errors.iter() {
-        println!("{}", error);
-    }
-    for expr in &expression_list {
-        match expr {
-            Ok(x) => println!("Parse Succeeded: {}", x),
-            Err(Some(x)) => println!("Parse Error: {}", x),
-            _ => (),
+    let statements = program(&mut tokens, &mut errors);
+    tokens.finish(errors, Ok(statements))
+}
+
+/// Parses a single expression, which must make up the whole of the scanned source.
+pub fn parse_expression(scanner: Scanner) -> Result<Expression, Vec<ParseError>> {
+    let mut tokens = Tokens::new(scanner);
+    let mut errors = Vec::new();
+    let result = expression(&mut tokens, &mut errors).and_then(|expression| {
+        consume(&mut tokens, &mut errors, Eof, "end of expression")?;
+        Ok(expression)
+    });
+    tokens.finish(errors, result)
+}
+
+/// The tokens still to be parsed. Scan errors are set aside as they're reached, so the parser
+/// only ever sees tokens.
+struct Tokens<'a> {
+    scanner: Scanner<'a>,
+    /// A token the parser took and then put back.
+    pushed_back: Option<SpannedToken>,
+    scan_errors: Vec<ScanError>,
+    /// How many statements and expressions enclose the one being parsed.
+    depth: usize,
+    /// How many errors there were when something nested too deeply and the rest of the source was
+    /// discarded. Any errors after these only complain that the source ended early.
+    abandoned: Option<usize>,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(scanner: Scanner<'a>) -> Tokens<'a> {
+        Tokens {
+            scanner,
+            pushed_back: None,
+            scan_errors: Vec::new(),
+            depth: 0,
+            abandoned: None,
+        }
+    }
+
+    /// The next token, without consuming it.
+    fn peek(&mut self) -> Option<&SpannedToken> {
+        if self.pushed_back.is_some() {
+            return self.pushed_back.as_ref();
+        }
+        while let Some(Err(_)) = self.scanner.peek() {
+            if let Some(Err(error)) = self.scanner.next() {
+                self.scan_errors.push(error);
+            }
         }
+        self.scanner.peek().and_then(|next| next.as_ref().ok())
+    }
+
+    fn pop(&mut self) -> Option<SpannedToken> {
+        self.peek()?;
+        self.pushed_back
+            .take()
+            .or_else(|| self.scanner.next().and_then(Result::ok))
+    }
+
+    fn push(&mut self, token: SpannedToken) {
+        self.pushed_back = Some(token);
     }
-    if errors.is_empty() && expression_list.len() == 1 {
-        if let Some(Ok(expr)) = expression_list.pop() {
-            Ok(expr)
+
+    /// Scans whatever the parser didn't reach, then reports the scan errors, or else the parse
+    /// errors, if there were any.
+    fn finish<T>(
+        mut self,
+        mut errors: Vec<ParseError>,
+        result: Result<T, ()>,
+    ) -> Result<T, Vec<ParseError>> {
+        while self.pop().is_some() {}
+        if let Some(len) = self.abandoned {
+            errors.truncate(len);
+        }
+        if !self.scan_errors.is_empty() {
+            Err(self.scan_errors.into_iter().map(ParseError::from).collect())
+        } else if errors.is_empty() {
+            result.map_err(|_| errors)
         } else {
-            panic!("Expression list unexpectedly empty")
+            Err(errors)
+        }
+    }
+}
+
+fn program(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Vec<Stmt> {
+    let mut statements = Vec::new();
+    while !matches!(peek(tokens), Some(Eof) | None) {
+        if let Ok(stmt) = declaration(tokens, errors) {
+            statements.push(stmt);
+        }
+    }
+    statements
+}
+
+/// Parses a declaration, synchronizing to the start of the next statement if it fails. An `Err`
+/// means the error has already been recorded and the caller should carry on.
+fn declaration(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Stmt, ()> {
+    let result = match peek(tokens) {
+        Some(Var) => {
+            tokens.pop();
+            var_declaration(tokens, errors)
+        }
+        Some(Class) => {
+            tokens.pop();
+            nested(tokens, errors, class_declaration)
+        }
+        Some(Fun) => {
+            tokens.pop();
+            nested(tokens, errors, |tokens, errors| {
+                function(tokens, errors, "function")
+            })
+            .map(Stmt::Function)
+        }
+        _ => statement(tokens, errors),
+    };
+    result.inspect_err(|_| synchronize(tokens))
+}
+
+fn class_declaration(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Stmt, ()> {
+    let name = consume_identifier(tokens, errors, "class name")?;
+    let superclass = if let Some(Less) = peek(tokens) {
+        tokens.pop();
+        let superclass = consume_identifier(tokens, errors, "superclass name")?;
+        Some(Variable::new(superclass))
+    } else {
+        None
+    };
+    consume(tokens, errors, LeftBrace, "'{' before class body")?;
+    let mut methods = Vec::new();
+    while !matches!(peek(tokens), Some(RightBrace | Eof) | None) {
+        methods.push(function(tokens, errors, "method")?);
+    }
+    consume(tokens, errors, RightBrace, "'}' after class body")?;
+    Ok(Stmt::class(name, superclass, methods))
+}
+
+/// Parses a function's name, parameters and body, assuming any leading keyword has already been
+/// consumed. `kind` describes the function in error messages.
+fn function(
+    tokens: &mut Tokens,
+    errors: &mut Vec<ParseError>,
+    kind: &str,
+) -> Result<Rc<Function>, ()> {
+    let name = consume_identifier(tokens, errors, &format!("{} name", kind))?;
+    consume(
+        tokens,
+        errors,
+        LeftParen,
+        &format!("'(' after {} name", kind),
+    )?;
+    let mut params = Vec::new();
+    if !matches!(peek(tokens), Some(RightParen)) {
+        loop {
+            let param = consume_identifier(tokens, errors, "parameter name")?;
+            if params.len() >= MAX_ARGUMENTS {
+                errors.push(ParseError::TooManyParameters { span: param.span });
+            }
+            params.push(param);
+            if let Some(Comma) = peek(tokens) {
+                tokens.pop();
+            } else {
+                break;
+            }
+        }
+    }
+    consume(tokens, errors, RightParen, "')' after parameters")?;
+    consume(
+        tokens,
+        errors,
+        LeftBrace,
+        &format!("'{{' before {} body", kind),
+    )?;
+    let body = block(tokens, errors)?;
+    Ok(Rc::new(Function { name, params, body }))
+}
+
+fn var_declaration(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Stmt, ()> {
+    let name = consume_identifier(tokens, errors, "variable name")?;
+    let initializer = if let Some(Equal) = peek(tokens) {
+        tokens.pop();
+        Some(expression(tokens, errors)?)
+    } else {
+        None
+    };
+    consume(tokens, errors, Semicolon, "';' after variable declaration")?;
+    Ok(Stmt::var(name, initializer))
+}
+
+fn statement(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Stmt, ()> {
+    nested(tokens, errors, statement_body)
+}
+
+fn statement_body(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Stmt, ()> {
+    match peek(tokens) {
+        Some(Print) => {
+            tokens.pop();
+            let value = expression(tokens, errors)?;
+            consume(tokens, errors, Semicolon, "';' after value")?;
+            Ok(Stmt::print(value))
+        }
+        Some(LeftBrace) => {
+            tokens.pop();
+            Ok(Stmt::block(block(tokens, errors)?))
+        }
+        Some(If) => {
+            tokens.pop();
+            if_statement(tokens, errors)
+        }
+        Some(While) => {
+            tokens.pop();
+            while_statement(tokens, errors)
+        }
+        Some(For) => {
+            tokens.pop();
+            for_statement(tokens, errors)
+        }
+        Some(Return) => {
+            let keyword = pop_val(tokens, errors)?;
+            let value = match peek(tokens) {
+                Some(Semicolon) => None,
+                _ => Some(expression(tokens, errors)?),
+            };
+            consume(tokens, errors, Semicolon, "';' after return value")?;
+            Ok(Stmt::return_value(keyword, value))
+        }
+        _ => {
+            let expr = expression(tokens, errors)?;
+            consume(tokens, errors, Semicolon, "';' after expression")?;
+            Ok(Stmt::expression(expr))
         }
+    }
+}
+
+fn if_statement(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Stmt, ()> {
+    consume(tokens, errors, LeftParen, "'(' after 'if'")?;
+    let condition = expression(tokens, errors)?;
+    consume(tokens, errors, RightParen, "')' after if condition")?;
+    let then_branch = statement(tokens, errors)?;
+    // A dangling else binds to the nearest if.
+    let else_branch = if let Some(Else) = peek(tokens) {
+        tokens.pop();
+        Some(statement(tokens, errors)?)
     } else {
-        panic!("Failed to parse")
+        None
+    };
+    Ok(Stmt::if_else(condition, then_branch, else_branch))
+}
+
+fn while_statement(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Stmt, ()> {
+    consume(tokens, errors, LeftParen, "'(' after 'while'")?;
+    let condition = expression(tokens, errors)?;
+    consume(tokens, errors, RightParen, "')' after condition")?;
+    let body = statement(tokens, errors)?;
+    Ok(Stmt::while_loop(condition, body))
+}
+
+/// Parses a `for` loop, desugaring it into a `while` loop wrapped in blocks for the initializer
+/// and increment.
+fn for_statement(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Stmt, ()> {
+    consume(tokens, errors, LeftParen, "'(' after 'for'")?;
+    let initializer = match peek(tokens) {
+        Some(Semicolon) => {
+            tokens.pop();
+            None
+        }
+        Some(Var) => {
+            tokens.pop();
+            Some(var_declaration(tokens, errors)?)
+        }
+        _ => {
+            let expr = expression(tokens, errors)?;
+            consume(tokens, errors, Semicolon, "';' after expression")?;
+            Some(Stmt::expression(expr))
+        }
+    };
+    let condition = match peek(tokens) {
+        Some(Semicolon) => Expression::literal_bool(true),
+        _ => expression(tokens, errors)?,
+    };
+    consume(tokens, errors, Semicolon, "';' after loop condition")?;
+    let increment = match peek(tokens) {
+        Some(RightParen) => None,
+        _ => Some(expression(tokens, errors)?),
+    };
+    consume(tokens, errors, RightParen, "')' after for clauses")?;
+
+    let mut body = statement(tokens, errors)?;
+    if let Some(increment) = increment {
+        body = Stmt::block(vec![body, Stmt::expression(increment)]);
+    }
+    body = Stmt::while_loop(condition, body);
+    if let Some(initializer) = initializer {
+        body = Stmt::block(vec![initializer, body]);
+    }
+    Ok(body)
+}
+
+/// Parses the declarations of a block, assuming the opening brace has already been consumed.
+fn block(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Vec<Stmt>, ()> {
+    let mut statements = Vec::new();
+    while !matches!(peek(tokens), Some(RightBrace | Eof) | None) {
+        if let Ok(stmt) = declaration(tokens, errors) {
+            statements.push(stmt);
+        }
     }
+    consume(tokens, errors, RightBrace, "'}' after block")?;
+    Ok(statements)
 }
 
-fn synchronize(tokens: &mut Vec<Token>) {
-    while let Some(token) = tokens.iter().peekable().peek() {
+/// Discards tokens until the start of the next statement, after a parse error at the next token.
+fn synchronize(tokens: &mut Tokens) {
+    // Always skip the offending token, so parsing makes progress.
+    match peek(tokens) {
+        Some(Eof) | None => return,
+        Some(Semicolon) => {
+            tokens.pop();
+            return;
+        }
+        Some(_) => {
+            tokens.pop();
+        }
+    }
+    while let Some(token) = peek(tokens) {
         match token {
             Semicolon => {
                 tokens.pop();
                 return;
             }
-            Class | Fun | Var | For | If | While | Print | Return => return,
+            Class | Fun | Var | For | If | While | Print | Return | Eof => return,
             _ => (),
         }
         tokens.pop();
     }
 }
 
-fn pop_val(tokens: &mut Vec<Token>, errors: &mut Vec<ParseError>) -> Result<Token, ()> {
-    match tokens.pop() {
-        Some(t) => Ok(t),
-        None => {
-            errors.push(ParseError::PopFailed);
-            return Err(());
-        }
-    }
+/// Parses something enclosed by whatever is being parsed, unless that's nested too deeply.
+fn nested<T>(
+    tokens: &mut Tokens,
+    errors: &mut Vec<ParseError>,
+    parse: impl FnOnce(&mut Tokens, &mut Vec<ParseError>) -> Result<T, ()>,
+) -> Result<T, ()> {
+    chain(tokens, errors, |tokens, errors| {
+        link(tokens, errors)?;
+        parse(tokens, errors)
+    })
 }
 
-fn expression(
-    tokens: &mut Vec<Token>,
+/// Parses a chain of operations, such as `a + b + c` or `a.b()`, each of which [`link`]s the
+/// chain so far one level deeper into the tree.
+fn chain<T>(
+    tokens: &mut Tokens,
     errors: &mut Vec<ParseError>,
-) -> Result<Expression, Option<Expression>> {
-    equality(tokens, errors)
+    parse: impl FnOnce(&mut Tokens, &mut Vec<ParseError>) -> Result<T, ()>,
+) -> Result<T, ()> {
+    let depth = tokens.depth;
+    let result = parse(tokens, errors);
+    tokens.depth = depth;
+    result
 }
 
-fn equality(
-    tokens: &mut Vec<Token>,
-    errors: &mut Vec<ParseError>,
-) -> Result<Expression, Option<Expression>> {
-    let mut expr = comparison(tokens, errors)?;
-    while let Some(BangEqual | Equal) = tokens.last() {
-        let operator = pop_val(tokens, errors).map_err(|_| expr.clone())?;
-        let right = comparison(tokens, errors)?;
-        expr = Expression::binary(expr, operator, right);
+/// Goes a level deeper into the tree, unless that nests it too deeply. Then there's no telling
+/// where the enclosing statements end, so the rest of the source is discarded.
+fn link(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<(), ()> {
+    if tokens.depth < MAX_NESTING {
+        tokens.depth += 1;
+        return Ok(());
     }
-    Ok(expr)
+    let span = tokens.peek().map(|next| next.span).unwrap_or_default();
+    errors.push(ParseError::TooDeeplyNested { span });
+    tokens.abandoned = Some(errors.len());
+    while !matches!(peek(tokens), Some(Eof) | None) {
+        tokens.pop();
+    }
+    Err(())
+}
+
+fn peek<'a>(tokens: &'a mut Tokens) -> Option<&'a Token> {
+    tokens.peek().map(|t| &t.token)
 }
 
-fn comparison(
-    tokens: &mut Vec<Token>,
+/// Pops the next token if it is `expected`, otherwise records an error and leaves it in place.
+fn consume(
+    tokens: &mut Tokens,
     errors: &mut Vec<ParseError>,
-) -> Result<Expression, Option<Expression>> {
-    let mut expr = term(tokens, errors)?;
-    while let Some(Greater | GreaterEqual | Less | LessEqual) = tokens.last() {
-        let operator = pop_val(tokens, errors).map_err(|_| expr.clone())?;
-        let right = term(tokens, errors)?;
-        expr = Expression::binary(expr, operator, right);
+    expected: Token,
+    description: &str,
+) -> Result<SpannedToken, ()> {
+    match tokens.peek() {
+        Some(next) if next.token == expected => pop_val(tokens, errors),
+        Some(next) => {
+            errors.push(ParseError::UnexpectedToken {
+                expected: description.to_string(),
+                found: next.token.clone(),
+                span: next.span,
+            });
+            Err(())
+        }
+        None => {
+            errors.push(ParseError::PopFailed);
+            Err(())
+        }
     }
-    Ok(expr)
 }
 
-fn term(
-    tokens: &mut Vec<Token>,
+fn consume_identifier(
+    tokens: &mut Tokens,
     errors: &mut Vec<ParseError>,
-) -> Result<Expression, Option<Expression>> {
-    let mut expr = factor(tokens, errors)?;
-    while let Some(Minus | Plus) = tokens.last() {
-        let operator = pop_val(tokens, errors).map_err(|_| expr.clone())?;
-        let right = factor(tokens, errors)?;
-        expr = Expression::binary(expr, operator, right);
+    description: &str,
+) -> Result<SpannedToken, ()> {
+    match tokens.peek() {
+        Some(SpannedToken {
+            token: Identifier(_),
+            ..
+        }) => pop_val(tokens, errors),
+        Some(next) => {
+            errors.push(ParseError::UnexpectedToken {
+                expected: description.to_string(),
+                found: next.token.clone(),
+                span: next.span,
+            });
+            Err(())
+        }
+        None => {
+            errors.push(ParseError::PopFailed);
+            Err(())
+        }
     }
-    Ok(expr)
 }
 
-fn factor(
-    tokens: &mut Vec<Token>,
-    errors: &mut Vec<ParseError>,
-) -> Result<Expression, Option<Expression>> {
-    let mut expr = unary(tokens, errors)?;
-    while let Some(Slash | Star) = tokens.last() {
-        let operator = pop_val(tokens, errors).map_err(|_| expr.clone())?;
-        let right = unary(tokens, errors)?;
-        expr = Expression::binary(expr, operator, right);
+fn pop_val(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<SpannedToken, ()> {
+    match tokens.pop() {
+        Some(t) => Ok(t),
+        None => {
+            errors.push(ParseError::PopFailed);
+            Err(())
+        }
+    }
+}
+
+fn expression(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    nested(tokens, errors, assignment)
+}
+
+fn assignment(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    let expr = or(tokens, errors)?;
+    if let Some(Equal) = peek(tokens) {
+        let equals = pop_val(tokens, errors)?;
+        let value = nested(tokens, errors, assignment)?;
+        match expr {
+            Expression::Variable(variable) => {
+                return Ok(Expression::assign(variable.name, value));
+            }
+            Expression::Get(get) => return Ok(Expression::set(*get.object, get.name, value)),
+            _ => (),
+        }
+        // Report the bad target but keep parsing, the parser isn't in a confused state.
+        errors.push(ParseError::InvalidAssignmentTarget { span: equals.span });
     }
     Ok(expr)
 }
 
-fn unary(
-    tokens: &mut Vec<Token>,
-    errors: &mut Vec<ParseError>,
-) -> Result<Expression, Option<Expression>> {
-    if let Some(Bang | Minus) = tokens.last() {
-        let operator = pop_val(tokens, errors).map_err(|_| None)?;
-        let right = unary(tokens, errors)?;
+fn or(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    chain(tokens, errors, |tokens, errors| {
+        let mut expr = and(tokens, errors)?;
+        while let Some(Or) = peek(tokens) {
+            link(tokens, errors)?;
+            let operator = pop_val(tokens, errors)?;
+            let right = and(tokens, errors)?;
+            expr = Expression::logical(expr, operator, right);
+        }
+        Ok(expr)
+    })
+}
+
+fn and(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    chain(tokens, errors, |tokens, errors| {
+        let mut expr = equality(tokens, errors)?;
+        while let Some(And) = peek(tokens) {
+            link(tokens, errors)?;
+            let operator = pop_val(tokens, errors)?;
+            let right = equality(tokens, errors)?;
+            expr = Expression::logical(expr, operator, right);
+        }
+        Ok(expr)
+    })
+}
+
+fn equality(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    chain(tokens, errors, |tokens, errors| {
+        let mut expr = comparison(tokens, errors)?;
+        while let Some(BangEqual | EqualEqual) = peek(tokens) {
+            link(tokens, errors)?;
+            let operator = pop_val(tokens, errors)?;
+            let right = comparison(tokens, errors)?;
+            expr = Expression::binary(expr, operator, right);
+        }
+        Ok(expr)
+    })
+}
+
+fn comparison(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    chain(tokens, errors, |tokens, errors| {
+        let mut expr = term(tokens, errors)?;
+        while let Some(Greater | GreaterEqual | Less | LessEqual) = peek(tokens) {
+            link(tokens, errors)?;
+            let operator = pop_val(tokens, errors)?;
+            let right = term(tokens, errors)?;
+            expr = Expression::binary(expr, operator, right);
+        }
+        Ok(expr)
+    })
+}
+
+fn term(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    chain(tokens, errors, |tokens, errors| {
+        let mut expr = factor(tokens, errors)?;
+        while let Some(Minus | Plus) = peek(tokens) {
+            link(tokens, errors)?;
+            let operator = pop_val(tokens, errors)?;
+            let right = factor(tokens, errors)?;
+            expr = Expression::binary(expr, operator, right);
+        }
+        Ok(expr)
+    })
+}
+
+fn factor(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    chain(tokens, errors, |tokens, errors| {
+        let mut expr = unary(tokens, errors)?;
+        while let Some(Slash | Star) = peek(tokens) {
+            link(tokens, errors)?;
+            let operator = pop_val(tokens, errors)?;
+            let right = unary(tokens, errors)?;
+            expr = Expression::binary(expr, operator, right);
+        }
+        Ok(expr)
+    })
+}
+
+fn unary(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    if let Some(Bang | Minus) = peek(tokens) {
+        let operator = pop_val(tokens, errors)?;
+        let right = nested(tokens, errors, unary)?;
         return Ok(Expression::unary(operator, right));
     }
-    primary(tokens, errors)
+    call(tokens, errors)
+}
+
+fn call(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    chain(tokens, errors, |tokens, errors| {
+        let mut expr = primary(tokens, errors)?;
+        loop {
+            match peek(tokens) {
+                Some(LeftParen) => {
+                    link(tokens, errors)?;
+                    tokens.pop();
+                    expr = finish_call(tokens, errors, expr)?;
+                }
+                Some(Dot) => {
+                    link(tokens, errors)?;
+                    tokens.pop();
+                    let name = consume_identifier(tokens, errors, "property name after '.'")?;
+                    expr = Expression::get(expr, name);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    })
 }
 
-fn primary(
-    tokens: &mut Vec<Token>,
+/// Parses the arguments of a call to `callee`, assuming the opening parenthesis has already been
+/// consumed.
+fn finish_call(
+    tokens: &mut Tokens,
     errors: &mut Vec<ParseError>,
-) -> Result<Expression, Option<Expression>> {
-    let token = tokens.pop().unwrap();
+    callee: Expression,
+) -> Result<Expression, ()> {
+    let mut arguments = Vec::new();
+    if !matches!(peek(tokens), Some(RightParen)) {
+        loop {
+            if arguments.len() >= MAX_ARGUMENTS {
+                if let Some(next) = tokens.peek() {
+                    errors.push(ParseError::TooManyArguments { span: next.span });
+                }
+            }
+            arguments.push(expression(tokens, errors)?);
+            if let Some(Comma) = peek(tokens) {
+                tokens.pop();
+            } else {
+                break;
+            }
+        }
+    }
+    let paren = consume(tokens, errors, RightParen, "')' after arguments")?;
+    Ok(Expression::call(callee, paren, arguments))
+}
+
+fn primary(tokens: &mut Tokens, errors: &mut Vec<ParseError>) -> Result<Expression, ()> {
+    let SpannedToken { token, span } = pop_val(tokens, errors)?;
     if let False = token {
         return Ok(Expression::literal_bool(false));
     }
     if let True = token {
         return Ok(Expression::literal_bool(true));
     }
+    if let Nil = token {
+        return Ok(Expression::literal_nil());
+    }
     if let Number(n) = token {
         return Ok(Expression::literal_num(n));
     }
     if let String(s) = token {
         return Ok(Expression::literal_str(s));
     }
+    if let Interpolation(s) = token {
+        let mut parts = vec![Expression::literal_str(s)];
+        loop {
+            parts.push(expression(tokens, errors)?);
+            match peek(tokens) {
+                Some(Interpolation(s)) => {
+                    parts.push(Expression::literal_str(*s));
+                    tokens.pop();
+                }
+                Some(String(s)) => {
+                    parts.push(Expression::literal_str(*s));
+                    tokens.pop();
+                    return Ok(Expression::interpolation(parts));
+                }
+                Some(_) => {
+                    let next = tokens.peek().ok_or(())?;
+                    errors.push(ParseError::UnterminatedInterpolation {
+                        found: next.token.clone(),
+                        span: next.span,
+                    });
+                    return Err(());
+                }
+                None => {
+                    errors.push(ParseError::PopFailed);
+                    return Err(());
+                }
+            }
+        }
+    }
+    if let Identifier(_) = token {
+        return Ok(Expression::variable(SpannedToken { token, span }));
+    }
+    if let This = token {
+        return Ok(Expression::this(SpannedToken { token, span }));
+    }
+    if let Super = token {
+        consume(tokens, errors, Dot, "'.' after 'super'")?;
+        let method = consume_identifier(tokens, errors, "superclass method name")?;
+        return Ok(Expression::super_method(
+            SpannedToken { token, span },
+            method,
+        ));
+    }
     if let LeftParen = token {
         let expr = expression(tokens, errors)?;
-        if let Some(RightParen) = tokens.pop() {
-            return Ok(Expression::grouping(expr));
-        } else {
-            errors.push(ParseError::MissingRightParen);
-            return Err(Some(expr));
+        match tokens.peek() {
+            Some(SpannedToken {
+                token: RightParen, ..
+            }) => {
+                tokens.pop();
+                return Ok(Expression::grouping(expr));
+            }
+            Some(next) => {
+                errors.push(ParseError::MissingRightParen {
+                    found: next.token.clone(),
+                    span: next.span,
+                });
+                return Err(());
+            }
+            None => {
+                errors.push(ParseError::PopFailed);
+                return Err(());
+            }
         }
     }
-    errors.push(ParseError::ExpectedExpression);
-    Err(None)
+    errors.push(ParseError::ExpectedExpression {
+        found: token.clone(),
+        span,
+    });
+    // Put the token back, errors are reported at the next token and skipped by synchronize.
+    tokens.push(SpannedToken { token, span });
+    Err(())
 }
@@ -0,0 +1,210 @@
+use super::expressions::{Expression, Variable};
+use crate::scanner::tokens::SpannedToken;
+use std::{
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expression(ExpressionStatement),
+    Print(Print),
+    Var(Var),
+    Block(Block),
+    If(If),
+    While(While),
+    /// Function declarations are shared with the runtime functions created from them.
+    Function(Rc<Function>),
+    Return(Return),
+    Class(Class),
+}
+impl Stmt {
+    pub fn expression(expression: Expression) -> Stmt {
+        Stmt::Expression(ExpressionStatement { expression })
+    }
+    pub fn print(expression: Expression) -> Stmt {
+        Stmt::Print(Print { expression })
+    }
+    pub fn var(name: SpannedToken, initializer: Option<Expression>) -> Stmt {
+        Stmt::Var(Var { name, initializer })
+    }
+    pub fn block(statements: Vec<Stmt>) -> Stmt {
+        Stmt::Block(Block { statements })
+    }
+    pub fn if_else(condition: Expression, then_branch: Stmt, else_branch: Option<Stmt>) -> Stmt {
+        Stmt::If(If {
+            condition,
+            then_branch: Box::new(then_branch),
+            else_branch: else_branch.map(Box::new),
+        })
+    }
+    pub fn class(
+        name: SpannedToken,
+        superclass: Option<Variable>,
+        methods: Vec<Rc<Function>>,
+    ) -> Stmt {
+        Stmt::Class(Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+    pub fn return_value(keyword: SpannedToken, value: Option<Expression>) -> Stmt {
+        Stmt::Return(Return { keyword, value })
+    }
+    pub fn while_loop(condition: Expression, body: Stmt) -> Stmt {
+        Stmt::While(While {
+            condition,
+            body: Box::new(body),
+        })
+    }
+}
+impl Display for Stmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Expression(s) => Display::fmt(s, f),
+            Self::Print(s) => Display::fmt(s, f),
+            Self::Var(s) => Display::fmt(s, f),
+            Self::Block(s) => Display::fmt(s, f),
+            Self::If(s) => Display::fmt(s, f),
+            Self::While(s) => Display::fmt(s, f),
+            Self::Function(s) => Display::fmt(s, f),
+            Self::Return(s) => Display::fmt(s, f),
+            Self::Class(s) => Display::fmt(s, f),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpressionStatement {
+    pub expression: Expression,
+}
+impl Display for ExpressionStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(; {})", self.expression)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Print {
+    pub expression: Expression,
+}
+impl Display for Print {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(print {})", self.expression)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Var {
+    pub name: SpannedToken,
+    pub initializer: Option<Expression>,
+}
+impl Display for Var {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.initializer {
+            Some(initializer) => write!(f, "(var {} = {})", self.name, initializer),
+            None => write!(f, "(var {})", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub statements: Vec<Stmt>,
+}
+impl Display for Block {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(block")?;
+        for statement in &self.statements {
+            write!(f, " {}", statement)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct If {
+    pub condition: Expression,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+}
+impl Display for If {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.else_branch {
+            Some(else_branch) => write!(
+                f,
+                "(if {} {} else {})",
+                self.condition, self.then_branch, else_branch
+            ),
+            None => write!(f, "(if {} {})", self.condition, self.then_branch),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct While {
+    pub condition: Expression,
+    pub body: Box<Stmt>,
+}
+impl Display for While {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(while {} {})", self.condition, self.body)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: SpannedToken,
+    pub params: Vec<SpannedToken>,
+    pub body: Vec<Stmt>,
+}
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(fun {}(", self.name)?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        write!(f, ")")?;
+        for statement in &self.body {
+            write!(f, " {}", statement)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Return {
+    pub keyword: SpannedToken,
+    pub value: Option<Expression>,
+}
+impl Display for Return {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "(return {})", value),
+            None => write!(f, "(return)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Class {
+    pub name: SpannedToken,
+    pub superclass: Option<Variable>,
+    pub methods: Vec<Rc<Function>>,
+}
+impl Display for Class {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(class {}", self.name)?;
+        if let Some(superclass) = &self.superclass {
+            write!(f, " < {}", superclass)?;
+        }
+        for method in &self.methods {
+            write!(f, " {}", method)?;
+        }
+        write!(f, ")")
+    }
+}
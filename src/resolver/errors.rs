@@ -0,0 +1,21 @@
+use crate::scanner::tokens::Span;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResolveError {
+    #[error("Error: Can't read local variable '{name}' in its own initializer at {span}")]
+    ReadInOwnInitializer { name: String, span: Span },
+    #[error("Error: Already a variable named '{name}' in this scope at {span}")]
+    DuplicateLocal { name: String, span: Span },
+    #[error("Error: Can't return from top-level code at {span}")]
+    TopLevelReturn { span: Span },
+    #[error("Error: Can't return a value from an initializer at {span}")]
+    ReturnValueFromInitializer { span: Span },
+    #[error("Error: Can't use 'this' outside of a class at {span}")]
+    ThisOutsideClass { span: Span },
+    #[error("Error: Can't use 'super' outside of a class at {span}")]
+    SuperOutsideClass { span: Span },
+    #[error("Error: Can't use 'super' in a class with no superclass at {span}")]
+    SuperWithoutSuperclass { span: Span },
+    #[error("Error: A class can't inherit from itself at {span}")]
+    InheritFromSelf { span: Span },
+}
@@ -0,0 +1,281 @@
+use self::errors::ResolveError;
+use crate::{
+    parser::{
+        expressions::{ExprId, Expression},
+        statements::{Function, Stmt},
+    },
+    scanner::tokens::SpannedToken,
+    symbol::Symbol,
+};
+use ahash::AHashMap;
+
+pub mod errors;
+
+/// The number of scopes between each resolved variable reference and the scope declaring it.
+/// References that aren't in the table are globals.
+pub type Locals = AHashMap<ExprId, usize>;
+
+/// Statically resolves every variable reference in `statements` to the scope it refers to.
+pub fn resolve(statements: &[Stmt]) -> Result<Locals, Vec<ResolveError>> {
+    let mut resolver = Resolver::new();
+    resolver.resolve_statements(statements);
+    resolver.finish()
+}
+
+/// Statically resolves every variable reference in an expression evaluated at the top level.
+pub fn resolve_expression(expression: &Expression) -> Result<Locals, Vec<ResolveError>> {
+    let mut resolver = Resolver::new();
+    resolver.resolve_expression(expression);
+    resolver.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+struct Resolver {
+    /// Local scopes, innermost last. Each maps a name to whether its initializer has finished
+    /// resolving. The global scope isn't tracked.
+    scopes: Vec<AHashMap<Symbol, bool>>,
+    locals: Locals,
+    errors: Vec<ResolveError>,
+    /// The kind of function currently being resolved.
+    function: FunctionKind,
+    /// The kind of class currently being resolved.
+    class: ClassKind,
+}
+
+impl Resolver {
+    fn new() -> Resolver {
+        Resolver {
+            scopes: Vec::new(),
+            locals: Locals::new(),
+            errors: Vec::new(),
+            function: FunctionKind::None,
+            class: ClassKind::None,
+        }
+    }
+
+    fn finish(self) -> Result<Locals, Vec<ResolveError>> {
+        if self.errors.is_empty() {
+            Ok(self.locals)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(s) => self.resolve_expression(&s.expression),
+            Stmt::Print(s) => self.resolve_expression(&s.expression),
+            Stmt::Var(s) => {
+                self.declare(&s.name);
+                if let Some(initializer) = &s.initializer {
+                    self.resolve_expression(initializer);
+                }
+                self.define(&s.name);
+            }
+            Stmt::Block(s) => {
+                self.scopes.push(AHashMap::new());
+                self.resolve_statements(&s.statements);
+                self.scopes.pop();
+            }
+            Stmt::If(s) => {
+                self.resolve_expression(&s.condition);
+                self.resolve_statement(&s.then_branch);
+                if let Some(else_branch) = &s.else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Stmt::While(s) => {
+                self.resolve_expression(&s.condition);
+                self.resolve_statement(&s.body);
+            }
+            Stmt::Function(s) => {
+                // Define the name eagerly so the function can refer to itself recursively.
+                self.declare(&s.name);
+                self.define(&s.name);
+                self.resolve_function(s, FunctionKind::Function);
+            }
+            Stmt::Return(s) => {
+                if self.function == FunctionKind::None {
+                    self.errors.push(ResolveError::TopLevelReturn {
+                        span: s.keyword.span,
+                    });
+                }
+                if let Some(value) = &s.value {
+                    if self.function == FunctionKind::Initializer {
+                        self.errors.push(ResolveError::ReturnValueFromInitializer {
+                            span: s.keyword.span,
+                        });
+                    }
+                    self.resolve_expression(value);
+                }
+            }
+            Stmt::Class(s) => {
+                let enclosing = std::mem::replace(&mut self.class, ClassKind::Class);
+                self.declare(&s.name);
+                self.define(&s.name);
+                if let Some(superclass) = &s.superclass {
+                    if superclass.name.token == s.name.token {
+                        self.errors.push(ResolveError::InheritFromSelf {
+                            span: superclass.name.span,
+                        });
+                    }
+                    self.class = ClassKind::Subclass;
+                    self.resolve_local(superclass.id, superclass.name.symbol());
+                    // Methods of a subclass close over a scope binding `super`.
+                    self.scopes.push(AHashMap::new());
+                    self.bind(Symbol::SUPER);
+                }
+                self.scopes.push(AHashMap::new());
+                self.bind(Symbol::THIS);
+                for method in &s.methods {
+                    let kind = if method.name.symbol() == Symbol::INIT {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    self.resolve_function(method, kind);
+                }
+                self.scopes.pop();
+                if s.superclass.is_some() {
+                    self.scopes.pop();
+                }
+                self.class = enclosing;
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, function: &Function, kind: FunctionKind) {
+        let enclosing = std::mem::replace(&mut self.function, kind);
+        self.scopes.push(AHashMap::new());
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(&function.body);
+        self.scopes.pop();
+        self.function = enclosing;
+    }
+
+    fn resolve_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::LiteralNil
+            | Expression::LiteralStr(_)
+            | Expression::LiteralNum(_)
+            | Expression::LiteralBool(_) => (),
+            Expression::Unary(e) => self.resolve_expression(&e.right),
+            Expression::Binary(e) => {
+                self.resolve_expression(&e.left);
+                self.resolve_expression(&e.right);
+            }
+            Expression::Logical(e) => {
+                self.resolve_expression(&e.left);
+                self.resolve_expression(&e.right);
+            }
+            Expression::Grouping(e) => self.resolve_expression(&e.expression),
+            Expression::Interpolation(e) => {
+                for part in &e.parts {
+                    self.resolve_expression(part);
+                }
+            }
+            Expression::Call(e) => {
+                self.resolve_expression(&e.callee);
+                for argument in &e.arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expression::Variable(e) => {
+                let name = e.name.symbol();
+                if let Some(false) = self.scopes.last().and_then(|scope| scope.get(&name)) {
+                    self.errors.push(ResolveError::ReadInOwnInitializer {
+                        name: name.to_string(),
+                        span: e.name.span,
+                    });
+                }
+                self.resolve_local(e.id, name);
+            }
+            Expression::Assign(e) => {
+                self.resolve_expression(&e.value);
+                self.resolve_local(e.id, e.name.symbol());
+            }
+            Expression::Get(e) => self.resolve_expression(&e.object),
+            Expression::Set(e) => {
+                self.resolve_expression(&e.value);
+                self.resolve_expression(&e.object);
+            }
+            Expression::This(e) => {
+                if self.class == ClassKind::None {
+                    self.errors.push(ResolveError::ThisOutsideClass {
+                        span: e.keyword.span,
+                    });
+                }
+                self.resolve_local(e.id, Symbol::THIS);
+            }
+            Expression::Super(e) => match self.class {
+                ClassKind::None => self.errors.push(ResolveError::SuperOutsideClass {
+                    span: e.keyword.span,
+                }),
+                ClassKind::Class => self.errors.push(ResolveError::SuperWithoutSuperclass {
+                    span: e.keyword.span,
+                }),
+                ClassKind::Subclass => self.resolve_local(e.id, Symbol::SUPER),
+            },
+        }
+    }
+
+    /// Adds `name` to the innermost scope, marked as not yet ready to be read.
+    fn declare(&mut self, name: &SpannedToken) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let key = name.symbol();
+            if scope.contains_key(&key) {
+                self.errors.push(ResolveError::DuplicateLocal {
+                    name: key.to_string(),
+                    span: name.span,
+                });
+            }
+            scope.insert(key, false);
+        }
+    }
+
+    /// Marks `name` as initialized and ready to be read.
+    fn define(&mut self, name: &SpannedToken) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.symbol(), true);
+        }
+    }
+
+    /// Binds an implicit name like `this` in the innermost scope, ready to be read.
+    fn bind(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, true);
+        }
+    }
+
+    fn resolve_local(&mut self, id: ExprId, name: Symbol) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(&name) {
+                self.locals.insert(id, depth);
+                return;
+            }
+        }
+    }
+}
@@ -20,4 +20,29 @@ pub enum ScanError {
         line_index: usize,
         col_index: usize,
     },
+    #[error("Error: Invalid escape sequence '{}' at line {} col {}\n{} | {}\n", escape, line_index + 1, col_index + 1, line_index + 1,line_str)]
+    InvalidEscape {
+        escape: String,
+        line_str: String,
+        line_index: usize,
+        col_index: usize,
+    },
+    #[error("Error: Unterminated block comment at line {} col {}\n{} | {}\n", line_index + 1, col_index + 1, line_index + 1,line_str)]
+    UnterminatedComment {
+        line_str: String,
+        line_index: usize,
+        col_index: usize,
+    },
+    #[error("Error: Unterminated '${{' in string at line {} col {}\n{} | {}\n", line_index + 1, col_index + 1, line_index + 1,line_str)]
+    UnterminatedInterpolation {
+        line_str: String,
+        line_index: usize,
+        col_index: usize,
+    },
+    #[error("Error: Expected expression inside '${{}}' at line {} col {}\n{} | {}\n", line_index + 1, col_index + 1, line_index + 1,line_str)]
+    EmptyInterpolation {
+        line_str: String,
+        line_index: usize,
+        col_index: usize,
+    },
 }
@@ -3,187 +3,562 @@ pub mod tokens;
 
 use self::{
     errors::ScanError,
-    tokens::{Token, KEYWORDS},
+    tokens::{Span, SpannedToken, Token, KEYWORDS},
 };
+use crate::symbol::Symbol;
+use std::{borrow::Cow, collections::VecDeque};
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_xid::UnicodeXID;
 
-pub fn scan(source: &str) -> Result<Vec<Token>, Vec<ScanError>> {
-    let mut scan_errors = Vec::new();
-    let mut tokens = Vec::new();
-    let mut lines = source.lines().enumerate();
-    while let Some((mut line, mut line_string)) = lines.next() {
-        let mut graphemes = line_string.graphemes(true).enumerate().peekable();
-        while let Some((start, char)) = graphemes.next() {
-            match char {
-                "(" => tokens.push(Token::LeftParen),
-                ")" => tokens.push(Token::RightParen),
-                "{" => tokens.push(Token::LeftBrace),
-                "}" => tokens.push(Token::RightBrace),
-                "," => tokens.push(Token::Comma),
-                "." => tokens.push(Token::Dot),
-                "-" => tokens.push(Token::Minus),
-                "+" => tokens.push(Token::Plus),
-                ";" => tokens.push(Token::Semicolon),
-                "*" => tokens.push(Token::Star),
-                "!" => {
-                    if let Some((_, "=")) = graphemes.peek() {
-                        tokens.push(Token::BangEqual);
-                        graphemes.next();
-                    } else {
-                        tokens.push(Token::Bang);
-                    }
+/// Scans tokens on demand in a single pass over the source. Errors are yielded in place of the
+/// lexemes they were found in and scanning carries on after them. The last item is always a
+/// [`Token::Eof`].
+pub struct Scanner<'a> {
+    source: &'a str,
+    /// Byte offset of the next character to scan.
+    position: usize,
+    /// Zero-based index of the line `position` is on.
+    line: usize,
+    /// Byte offset the current line starts at.
+    line_start: usize,
+    /// The last byte offset on the current line whose column was measured, and its column, so
+    /// each line's graphemes are only segmented once.
+    measured: (usize, usize),
+    /// Each `${` still open inside a string, innermost last.
+    interpolations: Vec<Interpolation>,
+    /// Items scanned ahead of the caller, in order.
+    lookahead: VecDeque<Result<SpannedToken, ScanError>>,
+    /// Whether the `Eof` token has been scanned.
+    finished: bool,
+}
+
+/// A `${` whose expression hasn't been closed yet.
+struct Interpolation {
+    /// Braces opened and not yet closed inside the interpolated expression.
+    depth: usize,
+    /// Whether no tokens have been scanned inside the interpolated expression yet.
+    empty: bool,
+    line_str: String,
+    line_index: usize,
+    col_index: usize,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(source: &'a str) -> Scanner<'a> {
+        // Windows editors often start files with a byte order mark, which isn't part of the line.
+        let start = if source.starts_with('\u{feff}') {
+            '\u{feff}'.len_utf8()
+        } else {
+            0
+        };
+        // A leading shebang line lets scripts be run as executables.
+        let position = if source[start..].starts_with("#!") {
+            source[start..]
+                .find('\n')
+                .map_or(source.len(), |end| start + end)
+        } else {
+            start
+        };
+        Scanner {
+            source,
+            position,
+            line: 0,
+            line_start: start,
+            measured: (start, 0),
+            interpolations: Vec::new(),
+            lookahead: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// The next item, without consuming it.
+    pub fn peek(&mut self) -> Option<&Result<SpannedToken, ScanError>> {
+        self.peek_nth(0)
+    }
+
+    /// The item `n` places ahead of the next one, without consuming anything.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Result<SpannedToken, ScanError>> {
+        while self.lookahead.len() <= n && !self.finished {
+            self.scan_token();
+        }
+        self.lookahead.get(n)
+    }
+
+    /// Scans up to and including the next token, queueing it after any errors found on the way.
+    fn scan_token(&mut self) {
+        self.skip_whitespace();
+        let start = self.position;
+        let line = self.line;
+        let col = self.column(start);
+        let char = match self.advance() {
+            Some(char) => char,
+            None => return self.finish(),
+        };
+        if let Some(interpolation) = self.interpolations.last_mut() {
+            interpolation.empty &= char == '}';
+        }
+        let token = match char {
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '{' => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.depth += 1;
                 }
-                "=" => {
-                    if let Some((_, "=")) = graphemes.peek() {
-                        tokens.push(Token::EqualEqual);
-                        graphemes.next();
-                    } else {
-                        tokens.push(Token::Equal);
-                    }
+                Token::LeftBrace
+            }
+            '}' if !matches!(
+                self.interpolations.last(),
+                Some(Interpolation { depth: 0, .. })
+            ) =>
+            {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.depth -= 1;
                 }
-                "<" => {
-                    if let Some((_, "=")) = graphemes.peek() {
-                        tokens.push(Token::LessEqual);
-                        graphemes.next();
-                    } else {
-                        tokens.push(Token::Less);
+                Token::RightBrace
+            }
+            ',' => Token::Comma,
+            '.' => Token::Dot,
+            '-' => Token::Minus,
+            '+' => Token::Plus,
+            ';' => Token::Semicolon,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '!' if self.advance_if(|c| c == '=') => Token::BangEqual,
+            '!' => Token::Bang,
+            '=' if self.advance_if(|c| c == '=') => Token::EqualEqual,
+            '=' => Token::Equal,
+            '<' if self.advance_if(|c| c == '=') => Token::LessEqual,
+            '<' => Token::Less,
+            '>' if self.advance_if(|c| c == '=') => Token::GreaterEqual,
+            '>' => Token::Greater,
+            // A string, or the rest of one after the `}` closing an interpolated expression.
+            '"' | '}' => {
+                if char == '}' {
+                    if let Some(Interpolation {
+                        empty: true,
+                        line_str,
+                        line_index,
+                        col_index,
+                        ..
+                    }) = self.interpolations.pop()
+                    {
+                        self.lookahead.push_back(Err(ScanError::EmptyInterpolation {
+                            line_str,
+                            line_index,
+                            col_index,
+                        }));
                     }
                 }
-                ">" => {
-                    if let Some((_, "=")) = graphemes.peek() {
-                        tokens.push(Token::GreaterEqual);
-                        graphemes.next();
-                    } else {
-                        tokens.push(Token::Greater);
-                    }
+                self.string(col)
+            }
+            // Only ASCII digits start number literals. Other Unicode digits can't be parsed.
+            '0'..='9' => match self.number(start) {
+                Some(number) => number,
+                None => return,
+            },
+            char if is_identifier_start(char) => self.identifier(start),
+            _ => {
+                // Report the whole grapheme, so a character isn't split from its combining marks.
+                let grapheme = self.source[start..].graphemes(true).next().unwrap_or("");
+                self.position = start + grapheme.len();
+                let error = ScanError::UnexpectedChar {
+                    char: grapheme.into(),
+                    line_str: self.line_str(),
+                    line_index: line,
+                    col_index: col,
+                };
+                return self.lookahead.push_back(Err(error));
+            }
+        };
+        self.lookahead.push_back(Ok(SpannedToken {
+            token,
+            span: Span {
+                offset: start,
+                line,
+                col,
+                len: self.position - start,
+            },
+        }));
+    }
+
+    /// Skips whitespace, including any line ending, and comments.
+    fn skip_whitespace(&mut self) {
+        loop {
+            match (self.peek_char(), self.peek_second()) {
+                (Some(char), _) if char.is_whitespace() => {
+                    self.advance();
                 }
-                "/" => {
-                    if let Some((_, "/")) = graphemes.peek() {
-                        graphemes.next();
-                        while graphemes.next_if(|&c| c.1 != "\n").is_some() {}
-                    } else {
-                        tokens.push(Token::Slash);
-                    }
+                (Some('/'), Some('/')) => while self.advance_if(|c| c != '\n') {},
+                (Some('/'), Some('*')) => self.block_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    fn block_comment(&mut self) {
+        let (line_index, col_index) = (self.line, self.column(self.position));
+        let line_str = self.line_str();
+        self.advance();
+        self.advance();
+        // Block comments nest, so count the comments still open.
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance() {
+                Some('/') if self.advance_if(|c| c == '*') => depth += 1,
+                Some('*') if self.advance_if(|c| c == '/') => depth -= 1,
+                Some(_) => (),
+                None => {
+                    let error = ScanError::UnterminatedComment {
+                        line_str,
+                        line_index,
+                        col_index,
+                    };
+                    return self.lookahead.push_back(Err(error));
                 }
-                "\"" => {
-                    let mut string_literal = String::from("");
-                    loop {
-                        if let Some((_, char)) = graphemes.next() {
-                            if char == "\"" {
-                                break;
-                            } else {
-                                string_literal = [string_literal, char.into()].concat();
-                            }
-                        } else if let Some((next_line, next_line_string)) = lines.next() {
-                            // This section makes multi-line strings possible
-                            line = next_line;
-                            line_string = next_line_string;
-                            graphemes = next_line_string.graphemes(true).enumerate().peekable();
-                        } else {
-                            scan_errors.push(ScanError::UnterminatedString {
-                                line_str: line_string.into(),
-                                line_index: line,
-                                col_index: start,
-                            });
-                            break;
+            }
+        }
+    }
+
+    /// Scans the rest of a string after its opening quote, up to the closing quote or the next
+    /// `${`.
+    fn string(&mut self, col: usize) -> Token {
+        let (line_index, line_str) = (self.line, self.line_str());
+        let mut string_literal = String::new();
+        loop {
+            match (self.peek_char(), self.peek_second()) {
+                (Some('"'), _) => {
+                    self.advance();
+                    break;
+                }
+                (Some('$'), Some('{')) => {
+                    let col_index = self.column(self.position);
+                    self.advance();
+                    self.advance();
+                    self.interpolations.push(Interpolation {
+                        depth: 0,
+                        empty: true,
+                        line_str: self.line_str(),
+                        line_index: self.line,
+                        col_index,
+                    });
+                    return Token::Interpolation(Symbol::intern(&string_literal));
+                }
+                (Some('\\'), _) => {
+                    let (line_index, col_index) = (self.line, self.column(self.position));
+                    self.advance();
+                    match self.escape() {
+                        Ok(escaped) => string_literal.push(escaped),
+                        Err(escape) => {
+                            let error = ScanError::InvalidEscape {
+                                escape,
+                                line_str: self.line_str(),
+                                line_index,
+                                col_index,
+                            };
+                            self.lookahead.push_back(Err(error));
                         }
                     }
-                    tokens.push(Token::String(string_literal));
                 }
-                " " => (), // Ignore whitespace
-                other_char => {
-                    if is_digit(other_char) {
-                        let mut number_literal = String::from(other_char);
-                        'outer: while let Some((_, next_char)) = graphemes.peek() {
-                            if is_digit(next_char) {
-                                number_literal = [number_literal, (*next_char).into()].concat();
-                                graphemes.next(); // Collect integer part
-                            } else if *next_char == "." {
-                                number_literal = [number_literal, (*next_char).into()].concat();
-                                graphemes.next(); // Collect the decimal
-                                while let Some((_, next_next_char)) = graphemes.peek() {
-                                    if is_digit(next_next_char) {
-                                        number_literal =
-                                            [number_literal, (*next_next_char).into()].concat();
-                                        graphemes.next(); // Collect the fractional part
-                                    } else {
-                                        break 'outer;
-                                    }
-                                }
-                            } else {
-                                break;
-                            }
-                        }
-                        match number_literal.parse() {
-                            Ok(num) => {
-                                let number: f64 = num;
-                                tokens.push(Token::Number(number));
-                            }
-                            Err(_) => scan_errors.push(ScanError::NumberLiteralParse {
-                                number: number_literal,
-                                line_str: line_string.into(),
-                                line_index: line,
-                                col_index: start,
-                            }),
-                        }
-                    } else if is_alpha(other_char) {
-                        let mut identifier = other_char;
-                        #[allow(unused_assignments)]
-                        let mut id_str = identifier.into();
-                        while let Some((_, next_char)) = graphemes.peek() {
-                            if is_alphanumeric(next_char) {
-                                id_str = [identifier, *next_char].concat();
-                                identifier = &id_str;
-                                graphemes.next();
-                            } else {
-                                break;
-                            }
+                // Strings may span lines. Line endings are normalized to `\n`, whatever the
+                // source uses.
+                (Some('\r'), Some('\n')) => {
+                    self.advance();
+                }
+                (Some(char), _) => {
+                    self.advance();
+                    string_literal.push(char);
+                }
+                (None, _) => {
+                    let error = ScanError::UnterminatedString {
+                        line_str,
+                        line_index,
+                        col_index: col,
+                    };
+                    self.lookahead.push_back(Err(error));
+                    break;
+                }
+            }
+        }
+        Token::String(Symbol::intern(&string_literal))
+    }
+
+    /// Scans the rest of an escape sequence after its backslash, returning the character it
+    /// stands for, or the text of the sequence if it's invalid or cut off by the end of the line.
+    fn escape(&mut self) -> Result<char, String> {
+        let mut escape = String::from("\\");
+        // A line ending, and inside `\u` a quote, always ends the escape, even a broken one.
+        let ends_escape = |c: char| c == '\r' || c == '\n';
+        let char = match self.peek_char() {
+            Some(char) if !ends_escape(char) => char,
+            _ => return Err(escape),
+        };
+        self.advance();
+        escape.push(char);
+        match char {
+            '"' => Ok('"'),
+            '$' => Ok('$'),
+            '\\' => Ok('\\'),
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            'u' => {
+                // `\u{XXXX}` takes one to six hex digits naming a Unicode scalar value.
+                let mut next = || match self.peek_char() {
+                    Some(char) if char != '"' && !ends_escape(char) => {
+                        self.advance();
+                        Some(char)
+                    }
+                    _ => None,
+                };
+                match next() {
+                    Some('{') => escape.push('{'),
+                    Some(other) => return Err(format!("{}{}", escape, other)),
+                    None => return Err(escape),
+                }
+                let mut digits = String::new();
+                loop {
+                    match next() {
+                        Some('}') => {
+                            escape.push('}');
+                            break;
                         }
-                        if let Some(keyword) = KEYWORDS.get(identifier) {
-                            tokens.push(keyword.clone());
-                        } else {
-                            tokens.push(Token::Identifier(String::from(identifier)));
+                        Some(digit) if digit.is_ascii_hexdigit() => {
+                            escape.push(digit);
+                            digits.push(digit);
                         }
-                    } else {
-                        scan_errors.push(ScanError::UnexpectedChar {
-                            char: other_char.into(),
-                            line_str: line_string.into(),
-                            line_index: line,
-                            col_index: start,
-                        });
+                        Some(other) => return Err(format!("{}{}", escape, other)),
+                        None => return Err(escape),
                     }
                 }
+                if digits.is_empty() || digits.len() > 6 {
+                    return Err(escape);
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(escape)
             }
+            _ => Err(escape),
         }
     }
-    tokens.push(Token::Eof);
-    if scan_errors.is_empty() {
-        Ok(tokens)
-    } else {
-        Err(scan_errors)
+
+    /// Scans the rest of a number literal, queueing an error instead if it's malformed.
+    fn number(&mut self, start: usize) -> Option<Token> {
+        let (line_index, col_index) = (self.line, self.column(start));
+        // Take the longest run that could belong to the literal, so malformed literals like
+        // `0xFG` or `1.` are reported whole.
+        while self.advance_if(is_identifier_continue) {}
+        let is_decimal = !matches!(
+            self.source.get(start..start + 2),
+            Some("0x" | "0X" | "0b" | "0B" | "0o" | "0O")
+        );
+        if is_decimal && self.advance_if(|c| c == '.') {
+            while self.advance_if(is_identifier_continue) {}
+        }
+        let lexeme = &self.source[start..self.position];
+        if is_decimal
+            && (lexeme.ends_with('e') || lexeme.ends_with('E'))
+            && self.advance_if(|c| c == '+' || c == '-')
+        {
+            while self.advance_if(is_identifier_continue) {}
+        }
+        let number_literal = &self.source[start..self.position];
+        match parse_number(number_literal) {
+            Some(number) => Some(Token::Number(number)),
+            None => {
+                let error = ScanError::NumberLiteralParse {
+                    number: number_literal.into(),
+                    line_str: self.line_str(),
+                    line_index,
+                    col_index,
+                };
+                self.lookahead.push_back(Err(error));
+                None
+            }
+        }
+    }
+
+    fn identifier(&mut self, start: usize) -> Token {
+        while self.advance_if(is_identifier_continue) {}
+        let identifier = normalize_identifier(&self.source[start..self.position]);
+        if let Some(keyword) = KEYWORDS.get(&*identifier) {
+            keyword.clone()
+        } else {
+            Token::Identifier(Symbol::intern(&identifier))
+        }
+    }
+
+    /// Queues errors for any interpolations left open, then the `Eof` token.
+    fn finish(&mut self) {
+        for interpolation in std::mem::take(&mut self.interpolations) {
+            self.lookahead
+                .push_back(Err(ScanError::UnterminatedInterpolation {
+                    line_str: interpolation.line_str,
+                    line_index: interpolation.line_index,
+                    col_index: interpolation.col_index,
+                }));
+        }
+        let end = self.source.len();
+        let span = Span {
+            offset: end,
+            line: self.line,
+            col: self.column(end),
+            len: 0,
+        };
+        self.lookahead.push_back(Ok(SpannedToken {
+            token: Token::Eof,
+            span,
+        }));
+        self.finished = true;
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.source[self.position..].chars().nth(1)
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let char = self.peek_char()?;
+        self.position += char.len_utf8();
+        if char == '\n' {
+            self.line += 1;
+            self.line_start = self.position;
+            self.measured = (self.position, 0);
+        }
+        Some(char)
+    }
+
+    /// Consumes the next character if it satisfies `predicate`, returning whether it did.
+    fn advance_if(&mut self, predicate: impl FnOnce(char) -> bool) -> bool {
+        match self.peek_char() {
+            Some(char) if predicate(char) => {
+                self.advance();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The column of byte `offset` on the current line, counting graphemes and expanding tabs.
+    /// Columns are measured on from the last one asked for, so scanning a line stays linear.
+    fn column(&mut self, offset: usize) -> usize {
+        let (from, col) = match self.measured {
+            (from, col) if self.line_start <= from && from <= offset => (from, col),
+            _ => (self.line_start, 0),
+        };
+        let col = self.source[from..offset]
+            .graphemes(true)
+            .fold(col, next_column);
+        self.measured = (offset, col);
+        col
+    }
+
+    /// The current line without its line ending, to show in errors.
+    fn line_str(&self) -> String {
+        let line = self.source[self.line_start..].lines().next().unwrap_or("");
+        line.into()
     }
 }
 
-fn is_digit(char: &str) -> bool {
-    if let Some(c) = char.chars().next() {
-        c.is_numeric()
-    } else {
-        false
+impl Iterator for Scanner<'_> {
+    type Item = Result<SpannedToken, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.peek();
+        self.lookahead.pop_front()
     }
 }
 
-fn is_alpha(char: &str) -> bool {
-    if let Some(c) = char.chars().next() {
-        c.is_alphabetic()
+/// Columns between tab stops.
+const TAB_WIDTH: usize = 4;
+
+/// The column following `grapheme` at column `col`. Tabs advance to the next tab stop, so columns
+/// match what an editor shows.
+fn next_column(col: usize, grapheme: &str) -> usize {
+    if grapheme == "\t" {
+        (col / TAB_WIDTH + 1) * TAB_WIDTH
     } else {
-        false
+        col + 1
+    }
+}
+
+/// Parses a number literal: decimal with an optional fraction and exponent, or an integer with a
+/// `0x`, `0b` or `0o` radix prefix. Any of these may separate digits with `_`.
+fn parse_number(literal: &str) -> Option<f64> {
+    let radix = match literal.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0b" | "0B") => 2,
+        Some("0o" | "0O") => 8,
+        _ => 10,
+    };
+    if radix != 10 {
+        let digits = &literal[2..];
+        if !is_digit_run(digits, radix) {
+            return None;
+        }
+        return u64::from_str_radix(&digits.replace('_', ""), radix)
+            .ok()
+            .map(|n| n as f64);
+    }
+    let (mantissa, exponent) = match literal.find(['e', 'E']) {
+        Some(index) => (&literal[..index], Some(&literal[index + 1..])),
+        None => (literal, None),
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let exponent = exponent.map(|e| e.strip_prefix(['+', '-']).unwrap_or(e));
+    let valid = is_digit_run(integer, 10)
+        && fraction.is_none_or(|fraction| is_digit_run(fraction, 10))
+        && exponent.is_none_or(|exponent| is_digit_run(exponent, 10));
+    if !valid {
+        return None;
     }
+    // Literals too large for a double would otherwise quietly become infinity.
+    literal
+        .replace('_', "")
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite())
 }
 
-fn is_alphanumeric(char: &str) -> bool {
-    if let Some(c) = char.chars().next() {
-        c.is_alphanumeric()
+/// Whether `digits` is a non-empty run of digits in `radix`, with `_` allowed only between them.
+fn is_digit_run(digits: &str, radix: u32) -> bool {
+    !digits.is_empty()
+        && !digits.starts_with('_')
+        && !digits.ends_with('_')
+        && digits.chars().all(|c| c == '_' || c.is_digit(radix))
+}
+
+/// Identifiers start with a Unicode `XID_Start` character or `_`.
+fn is_identifier_start(char: char) -> bool {
+    char == '_' || char.is_xid_start()
+}
+
+/// Identifiers continue with Unicode `XID_Continue` characters, which include `_`, digits and
+/// combining marks.
+fn is_identifier_continue(char: char) -> bool {
+    char.is_xid_continue()
+}
+
+/// Normalizes an identifier to NFC, so differently encoded but canonically equivalent names are
+/// the same identifier.
+#[cfg(feature = "nfc-identifiers")]
+fn normalize_identifier(identifier: &str) -> Cow<'_, str> {
+    use unicode_normalization::{is_nfc, UnicodeNormalization};
+    if is_nfc(identifier) {
+        Cow::Borrowed(identifier)
     } else {
-        false
+        Cow::Owned(identifier.nfc().collect())
     }
 }
+
+#[cfg(not(feature = "nfc-identifiers"))]
+fn normalize_identifier(identifier: &str) -> Cow<'_, str> {
+    Cow::Borrowed(identifier)
+}
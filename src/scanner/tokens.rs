@@ -1,5 +1,6 @@
 use std::fmt::{Display, Formatter};
 
+use crate::symbol::Symbol;
 use ahash::AHashMap;
 use lazy_static::lazy_static;
 
@@ -26,6 +27,48 @@ lazy_static! {
     };
 }
 
+/// Location of a token's lexeme in the source it was scanned from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    /// Byte offset of the start of the lexeme.
+    pub offset: usize,
+    /// Zero-based index of the line the lexeme starts on.
+    pub line: usize,
+    /// Zero-based column the lexeme starts at, counting graphemes and expanding tabs.
+    pub col: usize,
+    /// Length of the lexeme in bytes.
+    pub len: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {} col {}", self.line + 1, self.col + 1)
+    }
+}
+
+/// A [`Token`] along with the [`Span`] of source it was scanned from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    /// The name of an identifier token.
+    pub fn symbol(&self) -> Symbol {
+        match self.token {
+            Token::Identifier(symbol) => symbol,
+            ref other => panic!("Expected an identifier, found {}", other),
+        }
+    }
+}
+
+impl Display for SpannedToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.token, f)
+    }
+}
+
 // TODO: Would be good to make each of these more type constrained? E.g. maybe this enum contains
 // boxes that reference these variants as actual types which implement traits to separate, say,
 // operators from other tokens.
@@ -54,8 +97,11 @@ pub enum Token {
     Less,
     LessEqual,
     // Literals
-    Identifier(String),
-    String(String),
+    Identifier(Symbol),
+    String(Symbol),
+    /// A string segment ending at `${`, followed by the tokens of the interpolated expression and
+    /// then the rest of the string.
+    Interpolation(Symbol),
     Number(f64),
     // Keywords
     And,
@@ -101,7 +147,7 @@ impl Display for Token {
             Self::Less => write!(f, "<"),
             Self::LessEqual => write!(f, "<="),
             // Literals
-            Self::Identifier(s) | Self::String(s) => write!(f, "{}", s),
+            Self::Identifier(s) | Self::String(s) | Self::Interpolation(s) => write!(f, "{}", s),
             Self::Number(n) => write!(f, "{}", n),
             // Keywords
             Self::And => write!(f, "And"),
@@ -0,0 +1,116 @@
+use ahash::AHashMap;
+use lazy_static::lazy_static;
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+};
+
+/// A handle to an interned string. Equal strings share one symbol, so comparing and hashing
+/// symbols is as cheap as comparing integers. Reading a symbol's string doesn't touch the
+/// interner, so only scanning new source takes its lock.
+///
+/// Interned strings are never freed. Every distinct identifier and string literal in every
+/// program run in this process stays in memory, as does every name given to `Lox::set_global`,
+/// and none of it counts towards a session's heap limit. Hosts running many unrelated programs
+/// should expect the interner to grow with the total size of their source.
+#[derive(Clone, Copy)]
+pub struct Symbol {
+    id: u32,
+    string: &'static str,
+}
+
+/// Names the interpreter refers to itself, interned up front so they needn't be looked up.
+const PREINTERNED: &[&str] = &["this", "super", "init", ""];
+
+impl Symbol {
+    pub const THIS: Symbol = Symbol::preinterned(0);
+    pub const SUPER: Symbol = Symbol::preinterned(1);
+    pub const INIT: Symbol = Symbol::preinterned(2);
+    pub const EMPTY: Symbol = Symbol::preinterned(3);
+
+    const fn preinterned(id: u32) -> Symbol {
+        Symbol {
+            id,
+            string: PREINTERNED[id as usize],
+        }
+    }
+
+    /// Returns the symbol for `string`, interning it if it hasn't been seen before.
+    pub fn intern(string: &str) -> Symbol {
+        let mut interner = INTERNER.lock().expect("interner poisoned");
+        interner.intern(string)
+    }
+
+    /// Returns the symbol for `string` if it has been interned, without interning it.
+    pub fn get(string: &str) -> Option<Symbol> {
+        let interner = INTERNER.lock().expect("interner poisoned");
+        interner.symbols.get(string).copied()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.string
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Interned strings live for the rest of the program, so they're leaked and handed out as
+/// `'static` references.
+struct Interner {
+    symbols: AHashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(string) {
+            return symbol;
+        }
+        let string: &'static str = Box::leak(string.into());
+        let symbol = Symbol {
+            id: self.strings.len() as u32,
+            string,
+        };
+        self.strings.push(string);
+        self.symbols.insert(string, symbol);
+        symbol
+    }
+}
+
+lazy_static! {
+    static ref INTERNER: Mutex<Interner> = {
+        let mut interner = Interner {
+            symbols: AHashMap::new(),
+            strings: Vec::new(),
+        };
+        for string in PREINTERNED {
+            interner.intern(string);
+        }
+        Mutex::new(interner)
+    };
+}
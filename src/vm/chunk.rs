@@ -0,0 +1,174 @@
+use super::value::Value;
+use crate::scanner::tokens::Span;
+
+/// A single bytecode instruction. Operands follow the opcode in the chunk's code, most
+/// significant byte first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// Pushes constant `[index: u24]`.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// Pushes the local in stack slot `[slot: u8]` of the current frame.
+    GetLocal,
+    SetLocal,
+    /// Pushes the global named by constant `[index: u24]`.
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    /// Pushes the current closure's upvalue `[index: u8]`.
+    GetUpvalue,
+    SetUpvalue,
+    /// Replaces the instance on top of the stack with its property named by constant
+    /// `[index: u24]`.
+    GetProperty,
+    SetProperty,
+    /// Pops a superclass and replaces the instance below it with the superclass method named by
+    /// constant `[index: u24]`, bound to the instance.
+    GetSuper,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    /// Replaces the top `[count: u8]` values with the concatenation of their printed forms.
+    Interpolate,
+    Print,
+    /// Jumps forward by `[offset: u16]`.
+    Jump,
+    /// Jumps forward by `[offset: u16]` if the top of the stack is falsey, without popping it.
+    JumpIfFalse,
+    /// Jumps backward by `[offset: u16]`.
+    Loop,
+    /// Calls the value below `[argument count: u8]` arguments on the stack.
+    Call,
+    /// Wraps the function in constant `[index: u24]` in a closure, followed by a
+    /// `[is local: u8, index: u8]` pair for each upvalue it captures.
+    Closure,
+    /// Hoists the local on top of the stack into the heap before popping it.
+    CloseUpvalue,
+    Return,
+    /// Pushes a new class named by constant `[index: u24]`.
+    Class,
+    /// Copies the methods of the superclass below the subclass on the stack into the subclass.
+    Inherit,
+    /// Adds the closure on top of the stack to the class below it, as the method named by
+    /// constant `[index: u24]`.
+    Method,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 38] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Interpolate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::Inherit,
+        OpCode::Method,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+/// How many constants a chunk can hold. Indexes take three bytes, as a top-level script needs a
+/// constant for the name of every global it uses.
+pub const MAX_CONSTANTS: usize = 1 << 24;
+
+/// A compiled sequence of bytecode, with the constants it refers to and where in the source each
+/// instruction came from.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    /// Run-length encoded line table: each entry is the offset of the first byte emitted for a
+    /// span, in increasing order of offset.
+    spans: Vec<(usize, Span)>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, span: Span) {
+        if self.spans.last().is_none_or(|(_, last)| *last != span) {
+            self.spans.push((self.code.len(), span));
+        }
+        self.code.push(byte);
+    }
+
+    /// Adds a constant to the pool, reusing an existing slot for an identical value, and returns
+    /// its index.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        let identical = |constant: &Value| match (constant, value) {
+            // Compare bits so `0` and `-0` aren't merged.
+            (Value::Number(a), Value::Number(b)) => a.to_bits() == b.to_bits(),
+            (constant, value) => *constant == value,
+        };
+        match self.constants.iter().position(identical) {
+            Some(index) => index,
+            None => {
+                self.constants.push(value);
+                self.constants.len() - 1
+            }
+        }
+    }
+
+    /// Approximate bytes owned by the chunk, for the garbage collector's accounting.
+    pub fn size(&self) -> usize {
+        self.code.capacity()
+            + self.constants.capacity() * std::mem::size_of::<Value>()
+            + self.spans.capacity() * std::mem::size_of::<(usize, Span)>()
+    }
+
+    /// The source span of the instruction at `offset`.
+    pub fn span(&self, offset: usize) -> Span {
+        match self
+            .spans
+            .binary_search_by_key(&offset, |(start, _)| *start)
+        {
+            Ok(index) => self.spans[index].1,
+            Err(0) => Span::default(),
+            Err(index) => self.spans[index - 1].1,
+        }
+    }
+}
@@ -0,0 +1,635 @@
+use super::{
+    chunk::{Chunk, OpCode, MAX_CONSTANTS},
+    errors::CompileError,
+    heap::{Heap, ObjRef},
+    object::{Function, Object},
+    value::Value,
+};
+use crate::{
+    parser::{
+        expressions::Expression,
+        statements::{self, Stmt},
+    },
+    scanner::tokens::{Span, SpannedToken, Token},
+    symbol::Symbol,
+};
+use std::{convert::TryFrom, rc::Rc};
+
+/// Locals and upvalues are addressed with a single byte.
+const MAX_SLOTS: usize = u8::MAX as usize + 1;
+
+/// Compiles a resolved program into the function for its top-level script, allocated on `heap`.
+pub fn compile(statements: &[Stmt], heap: &mut Heap) -> Result<ObjRef, Vec<CompileError>> {
+    let mut compiler = Compiler::new(heap);
+    for statement in statements {
+        compiler.statement(statement);
+    }
+    compiler.finish()
+}
+
+/// Compiles a resolved expression into a script function that returns its value.
+pub fn compile_expression(
+    expression: &Expression,
+    heap: &mut Heap,
+) -> Result<ObjRef, Vec<CompileError>> {
+    let mut compiler = Compiler::new(heap);
+    compiler.expression(expression);
+    compiler.emit_op(OpCode::Return);
+    compiler.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug)]
+struct Local {
+    name: Symbol,
+    /// Scope depth of the local, or `None` until its initializer has been compiled.
+    depth: Option<usize>,
+    /// Whether a closure captures the local, so it must be hoisted when it goes out of scope.
+    is_captured: bool,
+}
+
+/// Where a closure finds a captured variable when it is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    /// Whether the variable is a local of the immediately enclosing function, rather than one of
+    /// its upvalues.
+    is_local: bool,
+    index: u8,
+}
+
+/// Compilation state for a function whose body is being compiled.
+struct FunctionState {
+    kind: FunctionKind,
+    name: Option<ObjRef>,
+    arity: usize,
+    chunk: Chunk,
+    /// Locals in stack slot order.
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(kind: FunctionKind, name: Option<ObjRef>) -> Self {
+        // Slot zero holds the callee, which methods expose as `this`.
+        let slot_zero = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => Symbol::THIS,
+            FunctionKind::Script | FunctionKind::Function => Symbol::EMPTY,
+        };
+        Self {
+            kind,
+            name,
+            arity: 0,
+            chunk: Chunk::default(),
+            locals: vec![Local {
+                name: slot_zero,
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+}
+
+struct Compiler<'h> {
+    heap: &'h mut Heap,
+    /// The function being compiled is last, preceded by the functions enclosing it.
+    states: Vec<FunctionState>,
+    errors: Vec<CompileError>,
+    /// Span of the source currently being compiled, attached to emitted instructions.
+    span: Span,
+}
+
+impl<'h> Compiler<'h> {
+    fn new(heap: &'h mut Heap) -> Compiler<'h> {
+        Compiler {
+            heap,
+            states: vec![FunctionState::new(FunctionKind::Script, None)],
+            errors: Vec::new(),
+            span: Span::default(),
+        }
+    }
+
+    /// Ends the top-level script, allocating its function if it compiled without errors.
+    fn finish(mut self) -> Result<ObjRef, Vec<CompileError>> {
+        let (function, _) = self.end_function();
+        if self.errors.is_empty() {
+            Ok(self.heap.alloc(Object::Function(function)))
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn state(&mut self) -> &mut FunctionState {
+        self.states.last_mut().expect("compiler has no function")
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(s) => {
+                self.expression(&s.expression);
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Print(s) => {
+                self.expression(&s.expression);
+                self.emit_op(OpCode::Print);
+            }
+            Stmt::Var(s) => {
+                self.span = s.name.span;
+                self.declare_variable(&s.name);
+                match &s.initializer {
+                    Some(initializer) => self.expression(initializer),
+                    None => self.emit_op(OpCode::Nil),
+                }
+                self.span = s.name.span;
+                self.define_variable(s.name.symbol());
+            }
+            Stmt::Block(s) => {
+                self.begin_scope();
+                for statement in &s.statements {
+                    self.statement(statement);
+                }
+                self.end_scope();
+            }
+            Stmt::If(s) => {
+                self.expression(&s.condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(&s.then_branch);
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump);
+                self.emit_op(OpCode::Pop);
+                if let Some(else_branch) = &s.else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump);
+            }
+            Stmt::While(s) => {
+                let loop_start = self.state().chunk.code.len();
+                self.expression(&s.condition);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(&s.body);
+                self.emit_loop(loop_start);
+                self.patch_jump(exit_jump);
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Function(s) => {
+                self.span = s.name.span;
+                self.declare_variable(&s.name);
+                // Functions may refer to themselves, so they're initialized before their body.
+                self.mark_initialized();
+                self.function(s, FunctionKind::Function);
+                self.span = s.name.span;
+                self.define_variable(s.name.symbol());
+            }
+            Stmt::Return(s) => {
+                match &s.value {
+                    Some(value) => self.expression(value),
+                    None => self.emit_implicit_return_value(),
+                }
+                self.span = s.keyword.span;
+                self.emit_op(OpCode::Return);
+            }
+            Stmt::Class(s) => self.class(s),
+        }
+    }
+
+    fn class(&mut self, class: &statements::Class) {
+        let name = class.name.symbol();
+        self.span = class.name.span;
+        let name_constant = self.identifier_constant(name);
+        self.declare_variable(&class.name);
+        self.emit_op_with_constant(OpCode::Class, name_constant);
+        self.define_variable(name);
+
+        if let Some(superclass) = &class.superclass {
+            self.get_variable(superclass.name.symbol(), superclass.name.span);
+            // Methods of a subclass close over a scope binding `super` to the superclass.
+            self.begin_scope();
+            self.add_local(Symbol::SUPER);
+            self.mark_initialized();
+            self.get_variable(name, class.name.span);
+            self.span = superclass.name.span;
+            self.emit_op(OpCode::Inherit);
+        }
+
+        self.get_variable(name, class.name.span);
+        for method in &class.methods {
+            let method_name = method.name.symbol();
+            let kind = if method_name == Symbol::INIT {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind);
+            self.span = method.name.span;
+            let constant = self.identifier_constant(method_name);
+            self.emit_op_with_constant(OpCode::Method, constant);
+        }
+        self.emit_op(OpCode::Pop);
+
+        if class.superclass.is_some() {
+            self.end_scope();
+        }
+    }
+
+    /// Compiles a function body and emits the instruction that creates a closure over it.
+    fn function(&mut self, declaration: &statements::Function, kind: FunctionKind) {
+        let name = self.heap.intern(declaration.name.symbol().as_str());
+        self.states.push(FunctionState::new(kind, Some(name)));
+        self.begin_scope();
+        for param in &declaration.params {
+            self.state().arity += 1;
+            self.span = param.span;
+            self.declare_variable(param);
+            self.mark_initialized();
+        }
+        for statement in &declaration.body {
+            self.statement(statement);
+        }
+        let (function, upvalues) = self.end_function();
+
+        self.span = declaration.name.span;
+        let function = self.heap.alloc(Object::Function(function));
+        let constant = self.make_constant(Value::Object(function));
+        self.emit_op_with_constant(OpCode::Closure, constant);
+        for upvalue in upvalues {
+            self.emit_byte(upvalue.is_local as u8);
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    /// Finishes the innermost function, returning it along with the upvalues it captures.
+    fn end_function(&mut self) -> (Function, Vec<UpvalueRef>) {
+        self.emit_implicit_return_value();
+        self.emit_op(OpCode::Return);
+        let state = self.states.pop().expect("compiler has no function");
+        let function = Function {
+            name: state.name,
+            arity: state.arity,
+            upvalue_count: state.upvalues.len(),
+            chunk: Rc::new(state.chunk),
+        };
+        (function, state.upvalues)
+    }
+
+    /// Initializers return `this`, everything else returns `nil` by default.
+    fn emit_implicit_return_value(&mut self) {
+        if self.state().kind == FunctionKind::Initializer {
+            self.emit_op_with(OpCode::GetLocal, 0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::LiteralNil => self.emit_op(OpCode::Nil),
+            Expression::LiteralBool(b) => {
+                self.emit_op(if b.value { OpCode::True } else { OpCode::False })
+            }
+            Expression::LiteralNum(n) => self.emit_constant(Value::Number(n.value)),
+            Expression::LiteralStr(s) => {
+                let string = self.heap.intern(s.value.as_str());
+                self.emit_constant(Value::Object(string));
+            }
+            Expression::Grouping(g) => self.expression(&g.expression),
+            Expression::Interpolation(i) => {
+                // Long strings are joined in batches, each batch's result starting the next one.
+                let mut pending = 0;
+                for part in &i.parts {
+                    self.expression(part);
+                    pending += 1;
+                    if pending == u8::MAX {
+                        self.emit_op_with(OpCode::Interpolate, pending);
+                        pending = 1;
+                    }
+                }
+                self.emit_op_with(OpCode::Interpolate, pending);
+            }
+            Expression::Unary(u) => {
+                self.expression(&u.right);
+                self.span = u.operator.span;
+                match u.operator.token {
+                    Token::Bang => self.emit_op(OpCode::Not),
+                    Token::Minus => self.emit_op(OpCode::Negate),
+                    ref other => unreachable!("Parsed invalid unary operator {}", other),
+                }
+            }
+            Expression::Binary(b) => {
+                self.expression(&b.left);
+                self.expression(&b.right);
+                self.span = b.operator.span;
+                match b.operator.token {
+                    Token::EqualEqual => self.emit_op(OpCode::Equal),
+                    Token::BangEqual => {
+                        self.emit_op(OpCode::Equal);
+                        self.emit_op(OpCode::Not);
+                    }
+                    Token::Greater => self.emit_op(OpCode::Greater),
+                    Token::GreaterEqual => self.emit_op(OpCode::GreaterEqual),
+                    Token::Less => self.emit_op(OpCode::Less),
+                    Token::LessEqual => self.emit_op(OpCode::LessEqual),
+                    Token::Plus => self.emit_op(OpCode::Add),
+                    Token::Minus => self.emit_op(OpCode::Subtract),
+                    Token::Star => self.emit_op(OpCode::Multiply),
+                    Token::Slash => self.emit_op(OpCode::Divide),
+                    ref other => unreachable!("Parsed invalid binary operator {}", other),
+                }
+            }
+            Expression::Logical(l) => {
+                self.expression(&l.left);
+                self.span = l.operator.span;
+                match l.operator.token {
+                    Token::And => {
+                        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        self.emit_op(OpCode::Pop);
+                        self.expression(&l.right);
+                        self.patch_jump(end_jump);
+                    }
+                    _ => {
+                        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                        let end_jump = self.emit_jump(OpCode::Jump);
+                        self.patch_jump(else_jump);
+                        self.emit_op(OpCode::Pop);
+                        self.expression(&l.right);
+                        self.patch_jump(end_jump);
+                    }
+                }
+            }
+            Expression::Variable(v) => self.get_variable(v.name.symbol(), v.name.span),
+            Expression::Assign(a) => {
+                self.expression(&a.value);
+                self.set_variable(a.name.symbol(), a.name.span);
+            }
+            Expression::Call(c) => {
+                self.expression(&c.callee);
+                for argument in &c.arguments {
+                    self.expression(argument);
+                }
+                self.span = c.paren.span;
+                // The parser limits calls to 255 arguments, so the count fits in a byte.
+                self.emit_op_with(OpCode::Call, c.arguments.len() as u8);
+            }
+            Expression::Get(g) => {
+                self.expression(&g.object);
+                self.span = g.name.span;
+                let name = self.identifier_constant(g.name.symbol());
+                self.emit_op_with_constant(OpCode::GetProperty, name);
+            }
+            Expression::Set(s) => {
+                self.expression(&s.object);
+                self.expression(&s.value);
+                self.span = s.name.span;
+                let name = self.identifier_constant(s.name.symbol());
+                self.emit_op_with_constant(OpCode::SetProperty, name);
+            }
+            Expression::This(t) => self.get_variable(Symbol::THIS, t.keyword.span),
+            Expression::Super(s) => {
+                self.get_variable(Symbol::THIS, s.keyword.span);
+                self.get_variable(Symbol::SUPER, s.keyword.span);
+                self.span = s.method.span;
+                let name = self.identifier_constant(s.method.symbol());
+                self.emit_op_with_constant(OpCode::GetSuper, name);
+            }
+        }
+    }
+
+    fn get_variable(&mut self, name: Symbol, span: Span) {
+        self.span = span;
+        self.access_variable(name, true);
+    }
+
+    /// Assigns the value on top of the stack to a variable, leaving it on the stack.
+    fn set_variable(&mut self, name: Symbol, span: Span) {
+        self.span = span;
+        self.access_variable(name, false);
+    }
+
+    /// Emits the instruction that gets or sets `name` from the current function.
+    fn access_variable(&mut self, name: Symbol, get: bool) {
+        let current = self.states.len() - 1;
+        if let Some(slot) = self.resolve_local(current, name) {
+            let op = if get {
+                OpCode::GetLocal
+            } else {
+                OpCode::SetLocal
+            };
+            self.emit_op_with(op, slot);
+        } else if let Some(index) = self.resolve_upvalue(current, name) {
+            let op = if get {
+                OpCode::GetUpvalue
+            } else {
+                OpCode::SetUpvalue
+            };
+            self.emit_op_with(op, index);
+        } else {
+            let op = if get {
+                OpCode::GetGlobal
+            } else {
+                OpCode::SetGlobal
+            };
+            let constant = self.identifier_constant(name);
+            self.emit_op_with_constant(op, constant);
+        }
+    }
+
+    fn resolve_local(&self, state: usize, name: Symbol) -> Option<u8> {
+        self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    /// Finds `name` in the functions enclosing `state`, threading it through each intermediate
+    /// function's upvalues.
+    fn resolve_upvalue(&mut self, state: usize, name: Symbol) -> Option<u8> {
+        let enclosing = state.checked_sub(1)?;
+        if let Some(slot) = self.resolve_local(enclosing, name) {
+            self.states[enclosing].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(
+                state,
+                UpvalueRef {
+                    is_local: true,
+                    index: slot,
+                },
+            ));
+        }
+        let index = self.resolve_upvalue(enclosing, name)?;
+        Some(self.add_upvalue(
+            state,
+            UpvalueRef {
+                is_local: false,
+                index,
+            },
+        ))
+    }
+
+    fn add_upvalue(&mut self, state: usize, upvalue: UpvalueRef) -> u8 {
+        let upvalues = &mut self.states[state].upvalues;
+        if let Some(index) = upvalues.iter().position(|existing| *existing == upvalue) {
+            return index as u8;
+        }
+        if upvalues.len() == MAX_SLOTS {
+            self.errors
+                .push(CompileError::TooManyUpvalues { span: self.span });
+            return 0;
+        }
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u8
+    }
+
+    /// Declares a local variable in the current scope. Globals are late bound, so aren't
+    /// declared.
+    fn declare_variable(&mut self, name: &SpannedToken) {
+        if self.state().scope_depth > 0 {
+            self.add_local(name.symbol());
+        }
+    }
+
+    fn add_local(&mut self, name: Symbol) {
+        if self.state().locals.len() == MAX_SLOTS {
+            self.errors
+                .push(CompileError::TooManyLocals { span: self.span });
+            return;
+        }
+        self.state().locals.push(Local {
+            name,
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    /// Marks the newest local as initialized, so it can be read.
+    fn mark_initialized(&mut self) {
+        let state = self.state();
+        if state.scope_depth > 0 {
+            if let Some(local) = state.locals.last_mut() {
+                local.depth = Some(state.scope_depth);
+            }
+        }
+    }
+
+    /// Binds the value on top of the stack to the variable just declared.
+    fn define_variable(&mut self, name: Symbol) {
+        if self.state().scope_depth > 0 {
+            // The value is already in the local's stack slot.
+            self.mark_initialized();
+        } else {
+            let constant = self.identifier_constant(name);
+            self.emit_op_with_constant(OpCode::DefineGlobal, constant);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().scope_depth += 1;
+    }
+
+    /// Discards the locals of the innermost scope, hoisting any captured by closures.
+    fn end_scope(&mut self) {
+        self.state().scope_depth -= 1;
+        loop {
+            let state = self.state();
+            let captured = match state.locals.last() {
+                Some(local) if local.depth.is_none_or(|depth| depth > state.scope_depth) => {
+                    local.is_captured
+                }
+                _ => break,
+            };
+            state.locals.pop();
+            self.emit_op(if captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+        }
+    }
+
+    fn identifier_constant(&mut self, name: Symbol) -> usize {
+        let name = self.heap.intern(name.as_str());
+        self.make_constant(Value::Object(name))
+    }
+
+    fn make_constant(&mut self, value: Value) -> usize {
+        let index = self.state().chunk.add_constant(value);
+        if index >= MAX_CONSTANTS {
+            self.errors
+                .push(CompileError::TooManyConstants { span: self.span });
+            return 0;
+        }
+        index
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let constant = self.make_constant(value);
+        self.emit_op_with_constant(OpCode::Constant, constant);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let span = self.span;
+        self.state().chunk.write(byte, span);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_op_with(&mut self, op: OpCode, operand: u8) {
+        self.emit_byte(op as u8);
+        self.emit_byte(operand);
+    }
+
+    /// Emits an instruction whose operand is the three byte index of a constant.
+    fn emit_op_with_constant(&mut self, op: OpCode, constant: usize) {
+        self.emit_byte(op as u8);
+        for &byte in &(constant as u32).to_be_bytes()[1..] {
+            self.emit_byte(byte);
+        }
+    }
+
+    /// Emits a jump with a placeholder offset, returning where to patch the offset in.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.state().chunk.code.len() - 2
+    }
+
+    /// Points the jump whose offset is at `offset` to the next instruction emitted.
+    fn patch_jump(&mut self, offset: usize) {
+        let code = &mut self.state().chunk.code;
+        let jump = code.len() - offset - 2;
+        match u16::try_from(jump) {
+            Ok(jump) => code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes()),
+            Err(_) => self
+                .errors
+                .push(CompileError::JumpTooLarge { span: self.span }),
+        }
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+        let jump = self.state().chunk.code.len() - loop_start + 2;
+        let jump = match u16::try_from(jump) {
+            Ok(jump) => jump,
+            Err(_) => {
+                self.errors
+                    .push(CompileError::LoopTooLarge { span: self.span });
+                0
+            }
+        };
+        for byte in jump.to_be_bytes() {
+            self.emit_byte(byte);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use super::{
+    chunk::{Chunk, OpCode},
+    heap::Heap,
+    object::Object,
+    value::Value,
+};
+
+/// Prints every instruction in `chunk`, followed by the chunks of any functions it defines.
+pub fn disassemble_chunk(chunk: &Chunk, name: &str, heap: &Heap) {
+    println!("== {} ==", name);
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let (text, next) = disassemble_instruction(chunk, offset, heap);
+        println!("{}", text);
+        offset = next;
+    }
+    for constant in &chunk.constants {
+        if let Value::Object(reference) = *constant {
+            if let Object::Function(function) = heap.get(reference) {
+                println!();
+                disassemble_chunk(&function.chunk, &heap.display(*constant).to_string(), heap);
+            }
+        }
+    }
+}
+
+/// Formats the instruction at `offset` with its operands and source line, returning the text and
+/// the offset of the next instruction.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize, heap: &Heap) -> (String, usize) {
+    let line = chunk.span(offset).line;
+    let prefix = if offset > 0 && chunk.span(offset - 1).line == line {
+        format!("{:04}    |", offset)
+    } else {
+        format!("{:04} {:4}", offset, line + 1)
+    };
+    let byte = chunk.code[offset];
+    let op = match OpCode::from_byte(byte) {
+        Some(op) => op,
+        None => return (format!("{} Unknown opcode {}", prefix, byte), offset + 1),
+    };
+    let name = format!("{:?}", op);
+    let operand = |index: usize| chunk.code[offset + index];
+    let index = || u32::from_be_bytes([0, operand(1), operand(2), operand(3)]) as usize;
+    let constant = || {
+        let constant = index();
+        let value = heap.display(chunk.constants[constant]);
+        format!("{:<16} {:4} '{}'", name, constant, value)
+    };
+    let jump = |forward: bool| {
+        let jump = u16::from_be_bytes([operand(1), operand(2)]) as usize;
+        let target = if forward {
+            offset + 3 + jump
+        } else {
+            offset + 3 - jump
+        };
+        format!("{:<16} {:4} -> {}", name, offset, target)
+    };
+    let (text, length) = match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => (constant(), 4),
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call
+        | OpCode::Interpolate => (format!("{:<16} {:4}", name, operand(1)), 2),
+        OpCode::Jump | OpCode::JumpIfFalse => (jump(true), 3),
+        OpCode::Loop => (jump(false), 3),
+        OpCode::Closure => {
+            let mut text = constant();
+            let mut length = 4;
+            if let Value::Object(function) = chunk.constants[index()] {
+                for _ in 0..heap.function(function).upvalue_count {
+                    let kind = if operand(length) == 1 {
+                        "local"
+                    } else {
+                        "upvalue"
+                    };
+                    text.push_str(&format!(
+                        "\n{:04}    |                     {} {}",
+                        offset + length,
+                        kind,
+                        operand(length + 1)
+                    ));
+                    length += 2;
+                }
+            }
+            (text, length)
+        }
+        OpCode::Nil
+        | OpCode::True
+        | OpCode::False
+        | OpCode::Pop
+        | OpCode::Equal
+        | OpCode::Greater
+        | OpCode::GreaterEqual
+        | OpCode::Less
+        | OpCode::LessEqual
+        | OpCode::Add
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide
+        | OpCode::Not
+        | OpCode::Negate
+        | OpCode::Print
+        | OpCode::CloseUpvalue
+        | OpCode::Return
+        | OpCode::Inherit => (name, 1),
+    };
+    (format!("{} {}", prefix, text), offset + length)
+}
@@ -0,0 +1,15 @@
+use crate::scanner::tokens::Span;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CompileError {
+    #[error("Error: Too many constants in one chunk at {span}")]
+    TooManyConstants { span: Span },
+    #[error("Error: Too many local variables in function at {span}")]
+    TooManyLocals { span: Span },
+    #[error("Error: Too many closure variables in function at {span}")]
+    TooManyUpvalues { span: Span },
+    #[error("Error: Too much code to jump over at {span}")]
+    JumpTooLarge { span: Span },
+    #[error("Error: Loop body too large at {span}")]
+    LoopTooLarge { span: Span },
+}
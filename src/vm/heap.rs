@@ -0,0 +1,348 @@
+use super::{
+    object::{Class, Closure, Function, Instance, Object, Upvalue},
+    value::Value,
+};
+use ahash::AHashMap;
+use std::fmt::{self, Display, Formatter};
+
+/// A handle to an object on the [`Heap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+/// Live bytes before the first collection is triggered.
+const INITIAL_GC_THRESHOLD: usize = 1024 * 1024;
+/// After a collection, the next one is triggered once live bytes grow by this factor.
+const GC_GROWTH_FACTOR: usize = 2;
+
+/// Owns every object the VM allocates. Objects refer to each other with [`ObjRef`] handles, so
+/// they can form cycles, which are reclaimed by a mark-and-sweep collector.
+#[derive(Debug)]
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    /// Slots of freed objects, reused by later allocations.
+    free: Vec<usize>,
+    /// Every string on the heap, so equal strings share one object. Strings aren't kept alive by
+    /// this table.
+    strings: AHashMap<String, ObjRef>,
+    /// Mark bits of the current collection, indexed like `objects`.
+    marks: Vec<bool>,
+    /// Bytes accounted to each object, indexed like `objects`.
+    sizes: Vec<usize>,
+    /// Marked objects whose references haven't been traced yet.
+    gray: Vec<ObjRef>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    /// Collect before every allocation, to flush out objects that aren't rooted.
+    stress: bool,
+    stats: GcStats,
+}
+
+/// Running totals of the garbage collector's work.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    pub collections: usize,
+    pub bytes_allocated: usize,
+    pub bytes_freed: usize,
+    pub objects_freed: usize,
+}
+
+impl Display for GcStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GC: {} collections, {} bytes allocated, {} bytes freed in {} objects, {} bytes live",
+            self.collections,
+            self.bytes_allocated,
+            self.bytes_freed,
+            self.objects_freed,
+            self.bytes_allocated - self.bytes_freed
+        )
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            objects: Vec::new(),
+            free: Vec::new(),
+            strings: AHashMap::new(),
+            marks: Vec::new(),
+            sizes: Vec::new(),
+            gray: Vec::new(),
+            bytes_allocated: 0,
+            next_gc: INITIAL_GC_THRESHOLD,
+            stress: false,
+            stats: GcStats::default(),
+        }
+    }
+}
+
+impl Heap {
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        let size = object.size();
+        self.bytes_allocated += size;
+        self.stats.bytes_allocated += size;
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                self.sizes[index] = size;
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marks.push(false);
+                self.sizes.push(size);
+                ObjRef(self.objects.len() - 1)
+            }
+        }
+    }
+
+    /// Updates the bytes accounted to an object after it has grown.
+    pub fn remeasure(&mut self, reference: ObjRef) {
+        let size = self.get(reference).size();
+        let accounted = &mut self.sizes[reference.0];
+        if size > *accounted {
+            self.bytes_allocated += size - *accounted;
+            self.stats.bytes_allocated += size - *accounted;
+            *accounted = size;
+        }
+    }
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    /// Bytes held by live objects, and by garbage not yet collected.
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    /// Whether enough has been allocated since the last collection to collect again.
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.bytes_allocated > self.next_gc
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+        if let Value::Object(reference) = value {
+            self.mark_object(reference);
+        }
+    }
+
+    pub fn mark_object(&mut self, reference: ObjRef) {
+        if !self.marks[reference.0] {
+            self.marks[reference.0] = true;
+            self.gray.push(reference);
+        }
+    }
+
+    /// Frees every object that isn't reachable from the objects marked as roots.
+    pub fn collect(&mut self) {
+        self.trace_references();
+        self.sweep();
+        self.next_gc = (self.bytes_allocated * GC_GROWTH_FACTOR).max(INITIAL_GC_THRESHOLD);
+        self.stats.collections += 1;
+    }
+
+    fn trace_references(&mut self) {
+        while let Some(reference) = self.gray.pop() {
+            let mut children = Vec::new();
+            match self.get(reference) {
+                Object::String(_)
+                | Object::Native(_)
+                | Object::Host(_)
+                | Object::NativeClass(_)
+                | Object::NativeInstance(_)
+                | Object::NativeMethod(_) => (),
+                Object::Function(function) => {
+                    children.extend(function.name.map(Value::Object));
+                    children.extend(function.chunk.constants.iter().copied());
+                }
+                Object::Closure(closure) => {
+                    children.push(Value::Object(closure.function));
+                    children.extend(closure.upvalues.iter().copied().map(Value::Object));
+                }
+                Object::Upvalue(Upvalue::Closed(value)) => children.push(*value),
+                Object::Upvalue(Upvalue::Open(_)) => (),
+                Object::Class(class) => {
+                    children.push(Value::Object(class.name));
+                    for (&name, &method) in &class.methods {
+                        children.push(Value::Object(name));
+                        children.push(Value::Object(method));
+                    }
+                }
+                Object::Instance(instance) => {
+                    children.push(Value::Object(instance.class));
+                    for (&name, &value) in &instance.fields {
+                        children.push(Value::Object(name));
+                        children.push(value);
+                    }
+                }
+                Object::BoundMethod(bound) => {
+                    children.push(bound.receiver);
+                    children.push(Value::Object(bound.method));
+                }
+            }
+            for child in children {
+                self.mark_value(child);
+            }
+        }
+    }
+
+    fn sweep(&mut self) {
+        let marks = &self.marks;
+        self.strings.retain(|_, reference| marks[reference.0]);
+        for index in 0..self.objects.len() {
+            if self.marks[index] {
+                self.marks[index] = false;
+            } else if self.objects[index].take().is_some() {
+                let size = std::mem::take(&mut self.sizes[index]);
+                self.bytes_allocated -= size;
+                self.stats.bytes_freed += size;
+                self.stats.objects_freed += 1;
+                self.free.push(index);
+            }
+        }
+    }
+
+    /// Returns the string object with these contents, allocating it if it doesn't exist yet.
+    pub fn intern(&mut self, string: &str) -> ObjRef {
+        match self.strings.get(string) {
+            Some(&reference) => reference,
+            None => self.intern_owned(string.to_string()),
+        }
+    }
+
+    /// The string object with these contents, if one has been interned.
+    pub fn interned(&self, string: &str) -> Option<ObjRef> {
+        self.strings.get(string).copied()
+    }
+
+    /// Like [`Heap::intern`], but takes ownership of a freshly built string.
+    pub fn intern_owned(&mut self, string: String) -> ObjRef {
+        if let Some(&reference) = self.strings.get(&string) {
+            return reference;
+        }
+        let reference = self.alloc(Object::String(string.clone()));
+        self.strings.insert(string, reference);
+        reference
+    }
+
+    pub fn get(&self, reference: ObjRef) -> &Object {
+        match &self.objects[reference.0] {
+            Some(object) => object,
+            None => panic!("Use of freed object {:?}", reference),
+        }
+    }
+
+    pub fn get_mut(&mut self, reference: ObjRef) -> &mut Object {
+        match &mut self.objects[reference.0] {
+            Some(object) => object,
+            None => panic!("Use of freed object {:?}", reference),
+        }
+    }
+
+    pub fn string(&self, reference: ObjRef) -> &str {
+        match self.get(reference) {
+            Object::String(string) => string,
+            other => panic!("Expected a string, found {:?}", other),
+        }
+    }
+
+    pub fn function(&self, reference: ObjRef) -> &Function {
+        match self.get(reference) {
+            Object::Function(function) => function,
+            other => panic!("Expected a function, found {:?}", other),
+        }
+    }
+
+    pub fn closure(&self, reference: ObjRef) -> &Closure {
+        match self.get(reference) {
+            Object::Closure(closure) => closure,
+            other => panic!("Expected a closure, found {:?}", other),
+        }
+    }
+
+    pub fn upvalue(&self, reference: ObjRef) -> &Upvalue {
+        match self.get(reference) {
+            Object::Upvalue(upvalue) => upvalue,
+            other => panic!("Expected an upvalue, found {:?}", other),
+        }
+    }
+
+    pub fn upvalue_mut(&mut self, reference: ObjRef) -> &mut Upvalue {
+        match self.get_mut(reference) {
+            Object::Upvalue(upvalue) => upvalue,
+            other => panic!("Expected an upvalue, found {:?}", other),
+        }
+    }
+
+    pub fn class(&self, reference: ObjRef) -> &Class {
+        match self.get(reference) {
+            Object::Class(class) => class,
+            other => panic!("Expected a class, found {:?}", other),
+        }
+    }
+
+    pub fn class_mut(&mut self, reference: ObjRef) -> &mut Class {
+        match self.get_mut(reference) {
+            Object::Class(class) => class,
+            other => panic!("Expected a class, found {:?}", other),
+        }
+    }
+
+    pub fn instance_mut(&mut self, reference: ObjRef) -> &mut Instance {
+        match self.get_mut(reference) {
+            Object::Instance(instance) => instance,
+            other => panic!("Expected an instance, found {:?}", other),
+        }
+    }
+
+    /// Formats `value` the way Lox prints it.
+    pub fn display(&self, value: Value) -> ValueDisplay<'_> {
+        ValueDisplay { heap: self, value }
+    }
+}
+
+pub struct ValueDisplay<'a> {
+    heap: &'a Heap,
+    value: Value,
+}
+
+impl Display for ValueDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let reference = match self.value {
+            Value::Nil => return write!(f, "nil"),
+            Value::Bool(b) => return write!(f, "{}", b),
+            Value::Number(n) => return write!(f, "{}", n),
+            Value::Object(reference) => reference,
+        };
+        match self.heap.get(reference) {
+            Object::String(string) => write!(f, "{}", string),
+            Object::Function(function) => match function.name {
+                Some(name) => write!(f, "<fn {}>", self.heap.string(name)),
+                None => write!(f, "<script>"),
+            },
+            Object::Native(_) | Object::Host(_) | Object::NativeMethod(_) => {
+                write!(f, "<native fn>")
+            }
+            Object::Closure(closure) => {
+                write!(f, "{}", self.heap.display(Value::Object(closure.function)))
+            }
+            Object::Upvalue(_) => write!(f, "upvalue"),
+            Object::Class(class) => write!(f, "{}", self.heap.string(class.name)),
+            Object::Instance(instance) => {
+                let class = self.heap.class(instance.class);
+                write!(f, "{} instance", self.heap.string(class.name))
+            }
+            Object::BoundMethod(bound) => {
+                write!(f, "{}", self.heap.display(Value::Object(bound.method)))
+            }
+            Object::NativeClass(class) => write!(f, "{}", class),
+            Object::NativeInstance(instance) => write!(f, "{}", instance),
+        }
+    }
+}
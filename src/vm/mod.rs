@@ -0,0 +1,949 @@
+use self::{
+    chunk::{Chunk, OpCode},
+    errors::CompileError,
+    heap::{Heap, ObjRef},
+    natives::NATIVES,
+    object::{BoundMethod, Class, Closure, Instance, Object, Upvalue},
+    pin::{Pin, Pins},
+    value::Value,
+};
+use crate::{
+    interpreter::error::{HostError, RuntimeError},
+    lox::{
+        self,
+        host::HostFunction,
+        limits::{InterruptHandle, Limits},
+        native::{ClassDefinition, NativeInstance, Property},
+        value::{next_owner, Object as HostObject, ObjectKind},
+    },
+    parser::{expressions::Expression, statements::Stmt},
+    scanner::tokens::{Span, Token},
+};
+use ahash::AHashMap;
+use std::{mem, rc::Rc};
+
+pub mod chunk;
+pub mod compiler;
+pub mod debug;
+pub mod errors;
+pub mod heap;
+pub mod natives;
+pub mod object;
+pub mod pin;
+pub mod value;
+
+/// An invocation of a closure that hasn't returned yet.
+struct CallFrame {
+    closure: ObjRef,
+    chunk: Rc<Chunk>,
+    /// Offset of the next instruction to execute in `chunk`.
+    ip: usize,
+    /// Stack index of the frame's slot zero, holding the callee.
+    slots: usize,
+}
+
+/// A stack-based virtual machine that executes programs compiled to bytecode.
+pub struct Vm {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: AHashMap<ObjRef, Value>,
+    /// Upvalues still pointing at stack slots, ordered by slot.
+    open_upvalues: Vec<ObjRef>,
+    init_string: ObjRef,
+    /// Objects handed out to the host, which stay alive while it holds them.
+    pins: Pins,
+    /// Distinguishes objects handed to the host from those of other backends.
+    id: usize,
+    limits: Limits,
+    interrupt: InterruptHandle,
+    /// Instructions executed so far in the current run.
+    steps: u64,
+    /// Print the bytecode of every program compiled.
+    disassemble: bool,
+    /// Print the stack and each instruction as it executes.
+    trace: bool,
+    /// Print garbage collector statistics after each run.
+    gc_stats: bool,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        let mut heap = Heap::default();
+        let mut globals = AHashMap::new();
+        for native in NATIVES {
+            let name = heap.intern(native.name);
+            let function = heap.alloc(Object::Native(*native));
+            globals.insert(name, Value::Object(function));
+        }
+        let init_string = heap.intern("init");
+        Self {
+            heap,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            open_upvalues: Vec::new(),
+            init_string,
+            pins: Pins::default(),
+            id: next_owner(),
+            limits: Limits::default(),
+            interrupt: InterruptHandle::default(),
+            steps: 0,
+            disassemble: false,
+            trace: false,
+            gc_stats: false,
+        }
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_disassemble(&mut self, disassemble: bool) {
+        self.disassemble = disassemble;
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Collects garbage before every allocation, rather than once enough has been allocated.
+    pub fn set_stress_gc(&mut self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    pub fn set_gc_stats(&mut self, gc_stats: bool) {
+        self.gc_stats = gc_stats;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Compiles a resolved program to a script function on this VM's heap.
+    pub fn compile(&mut self, statements: &[Stmt]) -> Result<ObjRef, Vec<CompileError>> {
+        let script = compiler::compile(statements, &mut self.heap)?;
+        if self.disassemble {
+            let chunk = &self.heap.function(script).chunk;
+            debug::disassemble_chunk(chunk, "<script>", &self.heap);
+        }
+        Ok(script)
+    }
+
+    /// Compiles a resolved expression to a script function that returns its value.
+    pub fn compile_expression(
+        &mut self,
+        expression: &Expression,
+    ) -> Result<ObjRef, Vec<CompileError>> {
+        let script = compiler::compile_expression(expression, &mut self.heap)?;
+        if self.disassemble {
+            let chunk = &self.heap.function(script).chunk;
+            debug::disassemble_chunk(chunk, "<expression>", &self.heap);
+        }
+        Ok(script)
+    }
+
+    /// Runs a script function returned by [`Vm::compile`] or [`Vm::compile_expression`],
+    /// returning the value it returns. Globals persist between runs.
+    ///
+    /// The returned value isn't rooted, so it must be used or pinned before anything else runs.
+    pub fn run(&mut self, script: ObjRef) -> Result<Value, RuntimeError> {
+        self.steps = 0;
+        // Keep the script rooted while its closure is allocated.
+        self.stack.push(Value::Object(script));
+        let closure = self.alloc(Object::Closure(Closure {
+            function: script,
+            upvalues: Vec::new(),
+        }));
+        self.stack[0] = Value::Object(closure);
+        let result = self.call(closure, 0).and_then(|_| self.execute());
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        if self.gc_stats {
+            println!("{}", self.heap.stats());
+        }
+        result
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let name = self.heap.interned(name)?;
+        self.globals.get(&name).copied()
+    }
+
+    /// Defines a global variable, or overwrites it if it already exists. Any object in `value`
+    /// must still be alive.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        // Keep the value rooted while the name is interned.
+        self.push(value);
+        let name = self.intern_owned(name.to_string());
+        let value = self.pop();
+        self.globals.insert(name, value);
+    }
+
+    /// Defines a host function as a global.
+    pub fn define_host_function(&mut self, host: HostFunction) {
+        let name = host.name.clone();
+        let function = self.alloc(Object::Host(host));
+        self.set_global(&name, Value::Object(function));
+    }
+
+    /// Defines a native class as a global.
+    pub fn define_native_class(&mut self, class: Rc<ClassDefinition>) {
+        let name = class.name().to_string();
+        let class = self.alloc(Object::NativeClass(class));
+        self.set_global(&name, Value::Object(class));
+    }
+
+    /// Converts a value for the host, pinning any object other than a string or native instance
+    /// for as long as the host holds on to it.
+    pub fn export(&mut self, value: Value) -> lox::value::Value {
+        let (value, pin) = self.lend(value);
+        if let Some(pin) = pin {
+            self.pins.keep(&pin);
+        }
+        value
+    }
+
+    /// Converts a value for the host without pinning it, for a call during which it stays rooted.
+    /// Returns the pin of any object handed out, for [`Vm::keep_held`] once the call returns.
+    fn lend(&self, value: Value) -> (lox::value::Value, Option<Rc<Pin>>) {
+        use lox::value::Value as Host;
+        let reference = match value {
+            Value::Nil => return (Host::Nil, None),
+            Value::Bool(b) => return (Host::Bool(b), None),
+            Value::Number(n) => return (Host::Number(n), None),
+            Value::Object(reference) => reference,
+        };
+        match self.heap.get(reference) {
+            Object::String(s) => (Host::String(s.clone()), None),
+            Object::NativeInstance(instance) => (Host::Native(instance.clone()), None),
+            _ => {
+                let pin = Pin::unpinned(reference);
+                let object = HostObject {
+                    owner: self.id,
+                    kind: ObjectKind::Vm {
+                        pin: pin.clone(),
+                        description: self.heap.display(value).to_string().into(),
+                    },
+                };
+                (Host::Object(object), Some(pin))
+            }
+        }
+    }
+
+    /// Pins the objects lent to the host that it held on to, as they may not stay rooted.
+    fn keep_held(&self, lent: impl IntoIterator<Item = Rc<Pin>>) {
+        for pin in lent {
+            // Every other reference to the pin is a handle the host kept.
+            if Rc::strong_count(&pin) > 1 {
+                self.pins.keep(&pin);
+            }
+        }
+    }
+
+    /// Converts a value from the host, which can't hold an object from another backend. A new
+    /// object isn't rooted, so it must be stored before anything else is allocated.
+    pub fn import(&mut self, value: lox::value::Value) -> Result<Value, HostError> {
+        use lox::value::Value as Host;
+        Ok(match value {
+            Host::Nil => Value::Nil,
+            Host::Bool(b) => Value::Bool(b),
+            Host::Number(n) => Value::Number(n),
+            Host::String(s) => Value::Object(self.intern_owned(s)),
+            Host::Native(instance) => Value::Object(self.alloc(Object::NativeInstance(instance))),
+            Host::Object(HostObject {
+                owner,
+                kind: ObjectKind::Vm { pin, .. },
+            }) if owner == self.id => Value::Object(pin.reference()),
+            Host::Object(_) => return Err(HostError::ForeignObject),
+        })
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            self.check_limits()?;
+            if self.trace {
+                self.trace_instruction();
+            }
+            let byte = self.read_byte();
+            let op = match OpCode::from_byte(byte) {
+                Some(op) => op,
+                None => panic!("Unknown opcode {}", byte),
+            };
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.push(self.stack[slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(&value) => self.push(value),
+                        None => return Err(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    self.globals.insert(name, self.peek(0));
+                    self.pop();
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0);
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let upvalue = self.frame_upvalue();
+                    let value = match *self.heap.upvalue(upvalue) {
+                        Upvalue::Open(slot) => self.stack[slot],
+                        Upvalue::Closed(value) => value,
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let upvalue = self.frame_upvalue();
+                    let value = self.peek(0);
+                    match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(slot) => {
+                            let slot = *slot;
+                            self.stack[slot] = value;
+                        }
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    if let Some(instance) = self.native_instance(self.peek(0)) {
+                        self.get_native_property(instance, name)?;
+                        continue;
+                    }
+                    let instance = match self.peek(0) {
+                        Value::Object(r) if matches!(self.heap.get(r), Object::Instance(_)) => r,
+                        _ => {
+                            return Err(RuntimeError::OnlyInstancesHaveProperties {
+                                span: self.span(),
+                            })
+                        }
+                    };
+                    let instance = self.heap.instance_mut(instance);
+                    match instance.fields.get(&name) {
+                        Some(&value) => {
+                            self.pop();
+                            self.push(value);
+                        }
+                        None => {
+                            let class = instance.class;
+                            self.bind_method(class, name)?;
+                        }
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    if let Some(instance) = self.native_instance(self.peek(1)) {
+                        // The value stays on the stack until the field has it.
+                        let (value, lent) = self.lend(self.peek(0));
+                        let result = instance.set(self.heap.string(name), value);
+                        self.keep_held(lent);
+                        let name = self.heap.string(name);
+                        result.map_err(|error| error.at(name, self.span()))?;
+                        let value = self.pop();
+                        self.pop();
+                        self.push(value);
+                        continue;
+                    }
+                    let instance = match self.peek(1) {
+                        Value::Object(r) if matches!(self.heap.get(r), Object::Instance(_)) => r,
+                        _ => {
+                            return Err(RuntimeError::OnlyInstancesHaveFields { span: self.span() })
+                        }
+                    };
+                    let value = self.pop();
+                    self.heap.instance_mut(instance).fields.insert(name, value);
+                    self.heap.remeasure(instance);
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let superclass = match self.pop() {
+                        Value::Object(r) => r,
+                        other => panic!("Expected a superclass, found {:?}", other),
+                    };
+                    self.bind_method(superclass, name)?;
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Bool(self.values_equal(a, b)));
+                }
+                OpCode::Greater => self.binary_op(Token::Greater, |a, b| Value::Bool(a > b))?,
+                OpCode::GreaterEqual => {
+                    self.binary_op(Token::GreaterEqual, |a, b| Value::Bool(a >= b))?
+                }
+                OpCode::Less => self.binary_op(Token::Less, |a, b| Value::Bool(a < b))?,
+                OpCode::LessEqual => {
+                    self.binary_op(Token::LessEqual, |a, b| Value::Bool(a <= b))?
+                }
+                OpCode::Add => self.add()?,
+                OpCode::Subtract => self.binary_op(Token::Minus, |a, b| Value::Number(a - b))?,
+                OpCode::Multiply => self.binary_op(Token::Star, |a, b| Value::Number(a * b))?,
+                OpCode::Divide => self.binary_op(Token::Slash, |a, b| Value::Number(a / b))?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Bool(!value.is_truthy()));
+                }
+                OpCode::Negate => match self.peek(0) {
+                    Value::Number(n) => {
+                        self.pop();
+                        self.push(Value::Number(-n));
+                    }
+                    _ => {
+                        return Err(RuntimeError::OperandMustBeNumber {
+                            operator: Token::Minus,
+                            span: self.span(),
+                        })
+                    }
+                },
+                OpCode::Interpolate => {
+                    let count = self.read_byte() as usize;
+                    let parts = &self.stack[self.stack.len() - count..];
+                    let string: String = parts
+                        .iter()
+                        .map(|&part| self.heap.display(part).to_string())
+                        .collect();
+                    // The parts stay on the stack, rooted, until the result is allocated.
+                    let result = self.intern_owned(string);
+                    self.stack.truncate(self.stack.len() - count);
+                    self.push(Value::Object(result));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    println!("{}", self.heap.display(value));
+                }
+                OpCode::Jump => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_short();
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let arg_count = self.read_byte() as usize;
+                    self.call_value(self.peek(arg_count), arg_count)?;
+                }
+                OpCode::Closure => {
+                    let function = match self.read_constant() {
+                        Value::Object(r) => r,
+                        other => panic!("Expected a function, found {:?}", other),
+                    };
+                    let upvalue_count = self.heap.function(function).upvalue_count;
+                    let mut upvalues = Vec::with_capacity(upvalue_count);
+                    for _ in 0..upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        let upvalue = if is_local {
+                            self.capture_upvalue(self.frame().slots + index)
+                        } else {
+                            self.heap.closure(self.frame().closure).upvalues[index]
+                        };
+                        upvalues.push(upvalue);
+                    }
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }));
+                    self.push(Value::Object(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("VM has no call frame");
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let class = self.alloc(Object::Class(Class {
+                        name,
+                        methods: AHashMap::new(),
+                    }));
+                    self.push(Value::Object(class));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Object(r) if matches!(self.heap.get(r), Object::Class(_)) => r,
+                        _ => return Err(RuntimeError::SuperclassMustBeClass { span: self.span() }),
+                    };
+                    let subclass = match self.peek(0) {
+                        Value::Object(r) => r,
+                        other => panic!("Expected a class, found {:?}", other),
+                    };
+                    let methods = self.heap.class(superclass).methods.clone();
+                    self.heap.class_mut(subclass).methods.extend(methods);
+                    self.heap.remeasure(subclass);
+                    self.pop();
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let method = match self.peek(0) {
+                        Value::Object(r) => r,
+                        other => panic!("Expected a method, found {:?}", other),
+                    };
+                    let class = match self.peek(1) {
+                        Value::Object(r) => r,
+                        other => panic!("Expected a class, found {:?}", other),
+                    };
+                    self.heap.class_mut(class).methods.insert(name, method);
+                    self.heap.remeasure(class);
+                    self.pop();
+                }
+            }
+        }
+    }
+
+    /// Prints the stack, then the instruction about to execute.
+    fn trace_instruction(&self) {
+        let stack: String = self
+            .stack
+            .iter()
+            .map(|&value| format!("[ {} ]", self.heap.display(value)))
+            .collect();
+        println!("          {}", stack);
+        let frame = self.frame();
+        let (instruction, _) = debug::disassemble_instruction(&frame.chunk, frame.ip, &self.heap);
+        println!("{}", instruction);
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), RuntimeError> {
+        let callee = match callee {
+            Value::Object(r) => r,
+            _ => return Err(RuntimeError::NotCallable { span: self.span() }),
+        };
+        let receiver_slot = self.stack.len() - arg_count - 1;
+        match self.heap.get(callee) {
+            Object::Closure(_) => self.call(callee, arg_count),
+            Object::Native(native) => {
+                let native = *native;
+                self.check_arity(native.arity, arg_count)?;
+                let result = (native.function)(&self.stack[receiver_slot + 1..]);
+                self.stack.truncate(receiver_slot);
+                self.push(result);
+                Ok(())
+            }
+            Object::Host(host) => {
+                let host = host.clone();
+                self.call_host(
+                    &host.name,
+                    host.arity,
+                    host.variadic,
+                    arg_count,
+                    |arguments| (host.function)(arguments),
+                )
+            }
+            Object::NativeClass(class) => {
+                let class = class.clone();
+                let (arity, variadic) = (class.arity(), class.is_variadic());
+                self.call_host(class.name(), arity, variadic, arg_count, |arguments| {
+                    class.construct(arguments)
+                })
+            }
+            Object::NativeMethod(method) => {
+                let method = method.clone();
+                let (arity, variadic) = (method.arity(), method.is_variadic());
+                self.call_host(method.name(), arity, variadic, arg_count, |arguments| {
+                    method.call(arguments)
+                })
+            }
+            Object::Class(class) => {
+                let initializer = class.methods.get(&self.init_string).copied();
+                let instance = self.alloc(Object::Instance(Instance {
+                    class: callee,
+                    fields: AHashMap::new(),
+                }));
+                self.stack[receiver_slot] = Value::Object(instance);
+                match initializer {
+                    Some(initializer) => self.call(initializer, arg_count),
+                    None => self.check_arity(0, arg_count),
+                }
+            }
+            Object::BoundMethod(bound) => {
+                let method = bound.method;
+                self.stack[receiver_slot] = bound.receiver;
+                self.call(method, arg_count)
+            }
+            _ => Err(RuntimeError::NotCallable { span: self.span() }),
+        }
+    }
+
+    /// Calls into the host with the arguments on top of the stack converted for it, and replaces
+    /// them and the callee with the result.
+    fn call_host(
+        &mut self,
+        name: &str,
+        arity: usize,
+        variadic: bool,
+        arg_count: usize,
+        function: impl FnOnce(Vec<lox::value::Value>) -> Result<lox::value::Value, HostError>,
+    ) -> Result<(), RuntimeError> {
+        if variadic && arg_count < arity {
+            return Err(RuntimeError::TooFewArguments {
+                expected: arity,
+                found: arg_count,
+                span: self.span(),
+            });
+        } else if !variadic {
+            self.check_arity(arity, arg_count)?;
+        }
+        let receiver_slot = self.stack.len() - arg_count - 1;
+        // The arguments stay on the stack, and so rooted, until the result is converted.
+        let (arguments, lent): (Vec<_>, Vec<_>) = (receiver_slot + 1..self.stack.len())
+            .map(|slot| self.lend(self.stack[slot]))
+            .unzip();
+        let result = function(arguments).and_then(|result| self.import(result));
+        self.keep_held(lent.into_iter().flatten());
+        let result = result.map_err(|error| error.at(name, self.span()))?;
+        self.stack.truncate(receiver_slot);
+        self.push(result);
+        Ok(())
+    }
+
+    /// The native instance `value` refers to, if it is one.
+    fn native_instance(&self, value: Value) -> Option<NativeInstance> {
+        match value {
+            Value::Object(r) => match self.heap.get(r) {
+                Object::NativeInstance(instance) => Some(instance.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Replaces the native instance on top of the stack with its property `name`.
+    fn get_native_property(
+        &mut self,
+        instance: NativeInstance,
+        name: ObjRef,
+    ) -> Result<(), RuntimeError> {
+        let name = self.heap.string(name).to_string();
+        let value = match instance.get(&name) {
+            Some(Property::Field(value)) => self
+                .import(value)
+                .map_err(|error| error.at(&name, self.span()))?,
+            Some(Property::Method(method)) => {
+                Value::Object(self.alloc(Object::NativeMethod(method)))
+            }
+            None => {
+                return Err(RuntimeError::UndefinedProperty {
+                    name,
+                    span: self.span(),
+                })
+            }
+        };
+        self.pop();
+        self.push(value);
+        Ok(())
+    }
+
+    /// Objects are only equal to themselves, except that native instances sharing a Rust value
+    /// are equal however many times they've been passed through the host.
+    fn values_equal(&self, a: Value, b: Value) -> bool {
+        a == b
+            || match (self.native_instance(a), self.native_instance(b)) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            }
+    }
+
+    /// Counts an instruction against the step limit, checks for an interrupt, and checks the heap
+    /// and stack still fit the heap limit once garbage is collected.
+    fn check_limits(&mut self) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        if let Some(limit) = self.limits.steps {
+            if self.steps > limit {
+                return Err(RuntimeError::StepLimitExceeded { limit });
+            }
+        }
+        if self.interrupt.take() {
+            return Err(RuntimeError::Interrupted);
+        }
+        if let Some(limit) = self.limits.heap_bytes {
+            if self.bytes_in_use() > limit {
+                self.collect_garbage();
+                if self.bytes_in_use() > limit {
+                    return Err(RuntimeError::HeapLimitExceeded { limit });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes counted against the heap limit: the objects on the heap, and the value stack and call
+    /// frames, which deep recursion grows without allocating any objects.
+    fn bytes_in_use(&self) -> usize {
+        self.heap.bytes_allocated()
+            + self.stack.len() * mem::size_of::<Value>()
+            + self.frames.len() * mem::size_of::<CallFrame>()
+    }
+
+    /// Pushes a frame to call `closure`, whose arguments are on top of the stack.
+    fn call(&mut self, closure: ObjRef, arg_count: usize) -> Result<(), RuntimeError> {
+        let function = self.heap.function(self.heap.closure(closure).function);
+        let (arity, chunk) = (function.arity, function.chunk.clone());
+        self.check_arity(arity, arg_count)?;
+        // The script's own frame isn't a call.
+        if self.frames.len() > self.limits.call_depth.unwrap_or(Limits::VM_CALL_DEPTH) {
+            return Err(RuntimeError::StackOverflow { span: self.span() });
+        }
+        self.frames.push(CallFrame {
+            closure,
+            chunk,
+            ip: 0,
+            slots: self.stack.len() - arg_count - 1,
+        });
+        Ok(())
+    }
+
+    fn check_arity(&self, expected: usize, found: usize) -> Result<(), RuntimeError> {
+        if expected == found {
+            Ok(())
+        } else {
+            Err(RuntimeError::ArityMismatch {
+                expected,
+                found,
+                span: self.span(),
+            })
+        }
+    }
+
+    /// Replaces the instance on top of the stack with its method `name`, bound to it.
+    fn bind_method(&mut self, class: ObjRef, name: ObjRef) -> Result<(), RuntimeError> {
+        let method = match self.heap.class(class).methods.get(&name) {
+            Some(&method) => method,
+            None => {
+                return Err(RuntimeError::UndefinedProperty {
+                    name: self.heap.string(name).to_string(),
+                    span: self.span(),
+                })
+            }
+        };
+        // The receiver stays on the stack until the bound method is allocated, to keep it rooted.
+        let receiver = self.peek(0);
+        let bound = self.alloc(Object::BoundMethod(BoundMethod { receiver, method }));
+        self.pop();
+        self.push(Value::Object(bound));
+        Ok(())
+    }
+
+    /// Allocates an object, first collecting garbage if it's due. Anything the caller still needs
+    /// must be reachable from the roots.
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.alloc(object)
+    }
+
+    fn intern_owned(&mut self, string: String) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.intern_owned(string)
+    }
+
+    /// Marks everything the VM can reach directly, then frees every object they don't lead to.
+    fn collect_garbage(&mut self) {
+        for &value in &self.stack {
+            self.heap.mark_value(value);
+        }
+        for frame in &self.frames {
+            self.heap.mark_object(frame.closure);
+        }
+        for (&name, &value) in &self.globals {
+            self.heap.mark_object(name);
+            self.heap.mark_value(value);
+        }
+        for &upvalue in &self.open_upvalues {
+            self.heap.mark_object(upvalue);
+        }
+        self.pins.mark(&mut self.heap);
+        self.heap.mark_object(self.init_string);
+        self.heap.collect();
+    }
+
+    /// Returns the open upvalue for stack `slot`, creating one if no closure has captured it yet.
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let heap = &self.heap;
+        let position = self.open_upvalues.binary_search_by_key(&slot, |&upvalue| {
+            match heap.upvalue(upvalue) {
+                Upvalue::Open(slot) => *slot,
+                Upvalue::Closed(_) => unreachable!("Closed upvalue in open list"),
+            }
+        });
+        match position {
+            Ok(index) => self.open_upvalues[index],
+            Err(index) => {
+                let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(slot)));
+                self.open_upvalues.insert(index, upvalue);
+                upvalue
+            }
+        }
+    }
+
+    /// Hoists every open upvalue at or above stack slot `last` off the stack.
+    fn close_upvalues(&mut self, last: usize) {
+        while let Some(&upvalue) = self.open_upvalues.last() {
+            let slot = match *self.heap.upvalue(upvalue) {
+                Upvalue::Open(slot) if slot >= last => slot,
+                _ => break,
+            };
+            *self.heap.upvalue_mut(upvalue) = Upvalue::Closed(self.stack[slot]);
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn add(&mut self) -> Result<(), RuntimeError> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(a), Value::Number(b)) => {
+                self.pop();
+                self.pop();
+                self.push(Value::Number(a + b));
+                Ok(())
+            }
+            (Value::Object(a), Value::Object(b))
+                if matches!(self.heap.get(a), Object::String(_))
+                    && matches!(self.heap.get(b), Object::String(_)) =>
+            {
+                let concatenated = [self.heap.string(a), self.heap.string(b)].concat();
+                let result = self.intern_owned(concatenated);
+                self.pop();
+                self.pop();
+                self.push(Value::Object(result));
+                Ok(())
+            }
+            _ => Err(RuntimeError::OperandsMustBeNumbersOrStrings {
+                operator: Token::Plus,
+                span: self.span(),
+            }),
+        }
+    }
+
+    fn binary_op(
+        &mut self,
+        operator: Token,
+        op: impl Fn(f64, f64) -> Value,
+    ) -> Result<(), RuntimeError> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(a), Value::Number(b)) => {
+                self.pop();
+                self.pop();
+                self.push(op(a, b));
+                Ok(())
+            }
+            _ => Err(RuntimeError::OperandsMustBeNumbers {
+                operator,
+                span: self.span(),
+            }),
+        }
+    }
+
+    fn undefined_variable(&self, name: ObjRef) -> RuntimeError {
+        RuntimeError::UndefinedVariable {
+            name: self.heap.string(name).to_string(),
+            span: self.span(),
+        }
+    }
+
+    /// The source span of the instruction being executed.
+    fn span(&self) -> Span {
+        let frame = self.frame();
+        frame.chunk.span(frame.ip.saturating_sub(1))
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("VM has no call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("VM has no call frame")
+    }
+
+    fn frame_upvalue(&mut self) -> ObjRef {
+        let index = self.read_byte() as usize;
+        self.heap.closure(self.frame().closure).upvalues[index]
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_short(&mut self) -> usize {
+        let high = self.read_byte();
+        let low = self.read_byte();
+        u16::from_be_bytes([high, low]) as usize
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let high = self.read_byte();
+        let index = self.read_short() | (high as usize) << 16;
+        self.frame().chunk.constants[index]
+    }
+
+    fn read_string(&mut self) -> ObjRef {
+        match self.read_constant() {
+            Value::Object(r) => r,
+            other => panic!("Expected a string constant, found {:?}", other),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("VM stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+}
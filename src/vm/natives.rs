@@ -0,0 +1,17 @@
+use super::{object::Native, value::Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The native functions defined as globals in every VM.
+pub const NATIVES: &[Native] = &[Native {
+    name: "clock",
+    arity: 0,
+    function: clock,
+}];
+
+/// Seconds since the Unix epoch, for timing scripts.
+fn clock(_: &[Value]) -> Value {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Value::Number(now.as_secs_f64())
+}
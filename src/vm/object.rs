@@ -0,0 +1,100 @@
+use super::{chunk::Chunk, heap::ObjRef, value::Value};
+use crate::lox::{
+    host::HostFunction,
+    native::{BoundMethod as NativeMethod, ClassDefinition, NativeInstance},
+};
+use ahash::AHashMap;
+use std::{mem::size_of, rc::Rc};
+
+/// Everything the VM allocates on its heap.
+#[derive(Debug)]
+pub enum Object {
+    String(String),
+    Function(Function),
+    Native(Native),
+    Host(HostFunction),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+    NativeClass(Rc<ClassDefinition>),
+    NativeInstance(NativeInstance),
+    NativeMethod(NativeMethod),
+}
+
+impl Object {
+    /// Approximate bytes owned by the object, for the garbage collector's accounting.
+    pub fn size(&self) -> usize {
+        let owned = match self {
+            Object::String(string) => string.capacity(),
+            Object::Function(function) => function.chunk.size(),
+            Object::Closure(closure) => closure.upvalues.capacity() * size_of::<ObjRef>(),
+            Object::Class(class) => class.methods.capacity() * size_of::<(ObjRef, ObjRef)>(),
+            Object::Instance(instance) => instance.fields.capacity() * size_of::<(ObjRef, Value)>(),
+            Object::Native(_)
+            | Object::Host(_)
+            | Object::Upvalue(_)
+            | Object::BoundMethod(_)
+            | Object::NativeClass(_)
+            | Object::NativeInstance(_)
+            | Object::NativeMethod(_) => 0,
+        };
+        size_of::<Object>() + owned
+    }
+}
+
+/// A compiled function. Functions are only called through a [`Closure`] wrapping them.
+#[derive(Debug)]
+pub struct Function {
+    /// The function's name, or `None` for the top-level script.
+    pub name: Option<ObjRef>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    /// Shared with call frames so the VM can read code without borrowing the heap.
+    pub chunk: Rc<Chunk>,
+}
+
+/// A function implemented in Rust and exposed to Lox as a global.
+#[derive(Debug, Clone, Copy)]
+pub struct Native {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Value,
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+}
+
+/// A variable captured by a closure.
+#[derive(Debug)]
+pub enum Upvalue {
+    /// The variable still lives on the stack, in this slot.
+    Open(usize),
+    /// The variable has gone out of scope, and was hoisted here.
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: ObjRef,
+    /// Method closures, keyed by interned name.
+    pub methods: AHashMap<ObjRef, ObjRef>,
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: ObjRef,
+    /// Field values, keyed by interned name.
+    pub fields: AHashMap<ObjRef, Value>,
+}
+
+/// A method closure accessed from an instance, remembering the instance to bind `this` to.
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
@@ -0,0 +1,67 @@
+use super::heap::{Heap, ObjRef};
+use ahash::AHashMap;
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
+
+/// How many pins each object handed to the host has.
+type Counts = RefCell<AHashMap<ObjRef, usize>>;
+
+/// Objects the host holds handles to, which must outlive anything scripts can reach.
+#[derive(Debug, Default)]
+pub struct Pins(Rc<Counts>);
+
+impl Pins {
+    /// Pins the object behind `pin` until the pin is dropped. Pinning it again does nothing.
+    pub fn keep(&self, pin: &Pin) {
+        let mut counts = pin.counts.borrow_mut();
+        if counts.upgrade().is_none() {
+            *self.0.borrow_mut().entry(pin.reference).or_insert(0) += 1;
+            *counts = Rc::downgrade(&self.0);
+        }
+    }
+
+    pub fn mark(&self, heap: &mut Heap) {
+        for &reference in self.0.borrow().keys() {
+            heap.mark_object(reference);
+        }
+    }
+}
+
+/// Keeps an object alive while the host holds it. A pin outliving its VM does nothing.
+#[derive(Debug)]
+pub struct Pin {
+    reference: ObjRef,
+    /// The counts the pin was added to, or nothing if it isn't pinning anything yet.
+    counts: RefCell<Weak<Counts>>,
+}
+
+impl Pin {
+    /// A pin that doesn't keep its object alive until [`Pins::keep`] is given it, for an object
+    /// that's rooted some other way for now.
+    pub fn unpinned(reference: ObjRef) -> Rc<Pin> {
+        Rc::new(Pin {
+            reference,
+            counts: RefCell::new(Weak::new()),
+        })
+    }
+
+    pub fn reference(&self) -> ObjRef {
+        self.reference
+    }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        if let Some(counts) = self.counts.get_mut().upgrade() {
+            let mut counts = counts.borrow_mut();
+            if let Some(count) = counts.get_mut(&self.reference) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&self.reference);
+                }
+            }
+        }
+    }
+}
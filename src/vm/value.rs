@@ -0,0 +1,18 @@
+use super::heap::ObjRef;
+
+/// A value on the VM's stack. Anything bigger than a number lives on the heap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    /// Strings are interned, so comparing references compares string contents.
+    Object(ObjRef),
+}
+
+impl Value {
+    /// Lox treats `nil` and `false` as falsey, and everything else as truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
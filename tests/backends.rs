@@ -0,0 +1,74 @@
+//! Runs every script in `tests/scripts` on each backend, and those in `tests/scripts/vm` on the
+//! VM only, checking the output against the script's `// expect: ` comments. A bare `// expect:`
+//! expects an empty line.
+
+use std::{fs, path::Path, process::Command};
+
+const EXPECT: &str = "// expect:";
+
+fn expected_output(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .filter_map(|line| line.find(EXPECT).map(|i| &line[i + EXPECT.len()..]))
+        .map(|expected| expected.strip_prefix(' ').unwrap_or(expected))
+        .collect()
+}
+
+fn run_script(path: &Path, flags: &[&str]) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_loxide"))
+        .args(flags)
+        .arg(path)
+        .output()
+        .expect("failed to run loxide");
+    let stdout = String::from_utf8(output.stdout).expect("output is not UTF-8");
+    let mut lines: Vec<String> = stdout.lines().map(str::to_string).collect();
+    assert_eq!(lines.pop().as_deref(), Some("Goodbye!"), "{:?}", path);
+    lines
+}
+
+fn check_scripts(directory: &str, flags: &[&str]) {
+    let scripts = Path::new(env!("CARGO_MANIFEST_DIR")).join(directory);
+    let mut paths: Vec<_> = fs::read_dir(scripts)
+        .expect("missing scripts directory")
+        .map(|entry| entry.expect("unreadable script").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    for path in paths {
+        let source = fs::read_to_string(&path).expect("unreadable script");
+        assert_eq!(
+            run_script(&path, flags),
+            expected_output(&source),
+            "{:?} with {:?}",
+            path,
+            flags
+        );
+    }
+}
+
+#[test]
+fn tree_walk_interpreter() {
+    check_scripts("tests/scripts", &[]);
+}
+
+#[test]
+fn bytecode_vm() {
+    check_scripts("tests/scripts", &["--vm"]);
+    check_scripts("tests/scripts/vm", &["--vm"]);
+}
+
+#[test]
+fn bytecode_vm_stress_gc() {
+    check_scripts("tests/scripts", &["--vm", "--stress-gc"]);
+    check_scripts("tests/scripts/vm", &["--vm", "--stress-gc"]);
+}
+
+#[test]
+fn disassembler_reads_wide_constant_indexes() {
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts/many_constants.lox");
+    let listing = run_script(&script, &["--disassemble"]);
+    assert!(listing
+        .iter()
+        .any(|line| line.ends_with("| GetGlobal         599 'v299'")));
+}
@@ -0,0 +1,411 @@
+//! Drives the embedding API on each backend.
+
+use loxide::{Limits, Lox, LoxError, NativeClass, RuntimeError, Span, Token, Value, VmOptions};
+use std::{cell::RefCell, rc::Rc, thread, time::Duration};
+
+fn check_session(mut lox: Lox) {
+    lox.run_source("var greeting = \"hi\"; fun twice(n) { return n * 2; }")
+        .expect("script failed");
+    assert_eq!(lox.get_global("greeting"), Some(Value::from("hi")));
+    assert_eq!(lox.get_global("missing"), None);
+
+    lox.set_global("n", 21.0).expect("set_global failed");
+    assert_eq!(
+        lox.eval_expression("twice(n)").expect("eval failed"),
+        Value::Number(42.0)
+    );
+    assert_eq!(
+        lox.eval_expression("greeting + \" there\"")
+            .expect("eval failed"),
+        Value::from("hi there")
+    );
+
+    // Objects can be handed back to the session they came from, but no other.
+    let twice = lox.get_global("twice").expect("twice is undefined");
+    assert_eq!(twice.to_string(), "<fn twice>");
+    lox.set_global("double", twice.clone())
+        .expect("set_global failed");
+    assert_eq!(
+        lox.eval_expression("double(4)").ok(),
+        Some(Value::Number(8.0))
+    );
+    assert!(matches!(
+        Lox::new().set_global("twice", twice),
+        Err(LoxError::ForeignObject)
+    ));
+
+    assert!(matches!(
+        lox.eval_expression("1 +"),
+        Err(LoxError::Parse(_))
+    ));
+    assert!(matches!(
+        lox.eval_expression("1; 2"),
+        Err(LoxError::Parse(_))
+    ));
+    assert!(matches!(
+        lox.eval_expression("this"),
+        Err(LoxError::Resolve(_))
+    ));
+    assert!(matches!(
+        lox.eval_expression("-\"a\""),
+        Err(LoxError::Runtime(RuntimeError::OperandMustBeNumber {
+            operator: Token::Minus,
+            span: Span {
+                line: 0,
+                col: 0,
+                ..
+            },
+        }))
+    ));
+    assert!(matches!(
+        lox.run_source("print \"a"),
+        Err(LoxError::Parse(_))
+    ));
+    // A failed run leaves the session usable.
+    assert_eq!(lox.eval_expression("n").ok(), Some(Value::Number(21.0)));
+}
+
+fn check_host_functions(mut lox: Lox) {
+    lox.register_function("hypot", |x: f64, y: f64| x.hypot(y));
+    lox.register_function("shout", |s: String| s.to_uppercase());
+    lox.register_function("either", |a: Option<f64>, b: f64| a.unwrap_or(b));
+    lox.register_function("sum", |ns: Vec<f64>| ns.iter().sum::<f64>());
+    lox.register_function("join", |sep: String, parts: Vec<Value>| {
+        let parts: Vec<String> = parts.iter().map(ToString::to_string).collect();
+        parts.join(&sep)
+    });
+    lox.register_function("nothing", || ());
+
+    let eval = |lox: &mut Lox, source: &str| lox.eval_expression(source).map_err(|e| e.to_string());
+    assert_eq!(eval(&mut lox, "hypot(3, 4)"), Ok(Value::Number(5.0)));
+    assert_eq!(eval(&mut lox, "shout(\"hey\")"), Ok(Value::from("HEY")));
+    assert_eq!(eval(&mut lox, "either(nil, 2)"), Ok(Value::Number(2.0)));
+    assert_eq!(eval(&mut lox, "either(1, 2)"), Ok(Value::Number(1.0)));
+    assert_eq!(eval(&mut lox, "sum()"), Ok(Value::Number(0.0)));
+    assert_eq!(eval(&mut lox, "sum(1, 2, 3)"), Ok(Value::Number(6.0)));
+    assert_eq!(
+        eval(&mut lox, "join(\", \", 1, true, nil, clock)"),
+        Ok(Value::from("1, true, nil, <native fn>"))
+    );
+    assert_eq!(eval(&mut lox, "nothing()"), Ok(Value::Nil));
+    assert_eq!(
+        eval(&mut lox, "hypot").map(|v| v.to_string()),
+        Ok("<native fn>".into())
+    );
+
+    assert_eq!(
+        eval(&mut lox, "hypot(3)"),
+        Err("Error: Expected 2 arguments but got 1 at line 1 col 8".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "join()"),
+        Err("Error: Expected at least 1 arguments but got 0 at line 1 col 6".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "hypot(3, \"4\")"),
+        Err("Error: Expected a number for argument 2 of 'hypot' at line 1 col 13".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "sum(1, nil)"),
+        Err("Error: Expected a number for argument 2 of 'sum' at line 1 col 11".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "either(true, 1)"),
+        Err("Error: Expected a number or nil for argument 1 of 'either' at line 1 col 15".into())
+    );
+
+    // Objects pass through host functions and back.
+    lox.register_function("identity", |v: Value| v);
+    lox.run_source("class Point {} var p = Point(); print identity(p) == p;")
+        .expect("script failed");
+    assert_eq!(eval(&mut lox, "identity(p) == p"), Ok(Value::Bool(true)));
+}
+
+struct Player {
+    name: String,
+    health: f64,
+}
+
+struct Bag {
+    any: Value,
+}
+
+fn check_native_classes(mut lox: Lox) {
+    let class = NativeClass::new("Player")
+        .constructor(|name: String| Player {
+            name,
+            health: 100.0,
+        })
+        .read_only("name", |p: &Player| p.name.clone())
+        .field(
+            "health",
+            |p: &Player| p.health,
+            |p: &mut Player, h: f64| p.health = h,
+        )
+        .method("hurt", |p: &mut Player, amount: f64| {
+            p.health -= amount;
+            p.health
+        })
+        .method("greet", |p: &mut Player, others: Vec<String>| {
+            format!("{} greets {}", p.name, others.join(" and "))
+        });
+    lox.register_class(&class);
+    lox.register_class(&NativeClass::<Player>::new("Ghost"));
+    lox.register_function("identity", |v: Value| v);
+
+    // The host shares its players with scripts.
+    let hero = Rc::new(RefCell::new(Player {
+        name: "Hero".into(),
+        health: 50.0,
+    }));
+    lox.set_global("hero", class.wrap(hero.clone()))
+        .expect("set_global failed");
+    lox.run_source(
+        "hero.health = 10; var hurt = hero.hurt; hurt(3); var p = Player(\"Ann\"); p.hurt(1);",
+    )
+    .expect("script failed");
+    assert_eq!(hero.borrow().health, 7.0);
+
+    let eval = |lox: &mut Lox, source: &str| lox.eval_expression(source).map_err(|e| e.to_string());
+    assert_eq!(eval(&mut lox, "p.health"), Ok(Value::Number(99.0)));
+    assert_eq!(eval(&mut lox, "p.name"), Ok(Value::from("Ann")));
+    assert_eq!(
+        eval(&mut lox, "p.greet(\"Bo\", \"Cy\")"),
+        Ok(Value::from("Ann greets Bo and Cy"))
+    );
+    assert_eq!(
+        eval(&mut lox, "p").map(|v| v.to_string()),
+        Ok("Player instance".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "Player").map(|v| v.to_string()),
+        Ok("Player".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "p.hurt").map(|v| v.to_string()),
+        Ok("<native fn>".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "identity(hero) == hero"),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(eval(&mut lox, "p == hero"), Ok(Value::Bool(false)));
+
+    let p = lox.get_global("p").expect("p is undefined");
+    match &p {
+        Value::Native(instance) => {
+            let player = instance.downcast::<Player>().expect("p isn't a Player");
+            assert_eq!(player.borrow().name, "Ann");
+        }
+        other => panic!("Expected a native instance, found {:?}", other),
+    }
+    // Native instances aren't tied to the session that made them.
+    let mut other = Lox::new_vm();
+    other.set_global("p", p).expect("set_global failed");
+    assert_eq!(
+        other.eval_expression("p.hurt(9)").ok(),
+        Some(Value::Number(90.0))
+    );
+
+    assert_eq!(
+        eval(&mut lox, "p.name = \"Bo\""),
+        Err("Error: Can't assign to read-only property 'name' at line 1 col 3".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "p.health = \"full\""),
+        Err("Error: Expected a number for 'health' at line 1 col 3".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "p.mana"),
+        Err("Error: Undefined property 'mana' at line 1 col 3".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "p.mana = 1"),
+        Err("Error: Undefined property 'mana' at line 1 col 3".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "p.hurt()"),
+        Err("Error: Expected 1 arguments but got 0 at line 1 col 8".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "p.hurt(nil)"),
+        Err("Error: Expected a number for argument 1 of 'hurt' at line 1 col 11".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "Player()"),
+        Err("Error: Expected 1 arguments but got 0 at line 1 col 8".into())
+    );
+    assert_eq!(
+        eval(&mut lox, "Ghost()"),
+        Err("Error: Native class 'Ghost' can't be constructed from Lox at line 1 col 7".into())
+    );
+}
+
+/// Checks each limit stops a runaway script with its own error, leaving the session usable.
+/// Only the VM has a heap to limit.
+fn check_limits(mut lox: Lox, limits_heap: bool) {
+    let runtime_error = |result: Result<(), LoxError>| match result {
+        Err(LoxError::Runtime(error)) => error,
+        other => panic!("Expected a runtime error, found {:?}", other),
+    };
+
+    lox.set_limits(Limits {
+        steps: Some(1000),
+        ..Limits::default()
+    })
+    .expect("set_limits failed");
+    assert!(matches!(
+        runtime_error(lox.run_source("while (true) {}")),
+        RuntimeError::StepLimitExceeded { limit: 1000 }
+    ));
+    // The budget is for each run.
+    lox.run_source("var i = 0; while (i < 20) i = i + 1;")
+        .expect("script failed");
+    lox.run_source("var j = 0; while (j < 20) j = j + 1;")
+        .expect("script failed");
+
+    lox.set_limits(Limits {
+        call_depth: Some(16),
+        ..Limits::default()
+    })
+    .expect("set_limits failed");
+    lox.run_source("fun down(n) { if (n > 0) down(n - 1); }")
+        .expect("script failed");
+    assert_eq!(
+        lox.run_source("down(100);").map_err(|e| e.to_string()),
+        Err("Error: Stack overflow at line 1 col 36".into())
+    );
+    lox.run_source("down(15);").expect("script failed");
+    // Deep recursion fails on the call depth, not by overflowing the host thread's stack.
+    lox.set_limits(Limits {
+        call_depth: Some(5000),
+        ..Limits::default()
+    })
+    .expect("set_limits failed");
+    assert_eq!(
+        lox.run_source("down(6000);").map_err(|e| e.to_string()),
+        Err("Error: Stack overflow at line 1 col 36".into())
+    );
+    lox.run_source("down(4000);").expect("script failed");
+
+    lox.set_limits(Limits::default())
+        .expect("set_limits failed");
+    let interrupt = lox.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        interrupt.interrupt();
+    });
+    assert!(matches!(
+        runtime_error(lox.run_source("while (true) {}")),
+        RuntimeError::Interrupted
+    ));
+    interrupter.join().expect("interrupting thread panicked");
+    assert_eq!(lox.eval_expression("1 + 1").ok(), Some(Value::Number(2.0)));
+
+    let heap_limit = lox.set_limits(Limits {
+        heap_bytes: Some(64 * 1024),
+        ..Limits::default()
+    });
+    if !limits_heap {
+        // The tree-walking interpreter can't measure its heap, so it refuses to limit it.
+        assert!(matches!(heap_limit, Err(LoxError::UnsupportedHeapLimit)));
+        return;
+    }
+    heap_limit.expect("set_limits failed");
+    assert!(matches!(
+        runtime_error(lox.run_source("var s = \"x\"; while (true) s = s + s;")),
+        RuntimeError::HeapLimitExceeded { limit: 65536 }
+    ));
+    // Deep recursion counts too, though it grows the stack without allocating any objects.
+    let locals: String = (0..250).map(|i| format!("var l{} = {};", i, i)).collect();
+    lox.run_source(&format!("fun dig() {{ {} dig(); }}", locals))
+        .expect("script failed");
+    assert!(matches!(
+        runtime_error(lox.run_source("dig();")),
+        RuntimeError::HeapLimitExceeded { limit: 65536 }
+    ));
+    // Garbage doesn't count against the limit.
+    lox.run_source("class P {} var k = 0; while (k < 5000) { var p = P(); k = k + 1; }")
+        .expect("script failed");
+}
+
+/// Checks objects handed to the host stay alive only while it holds them, under a heap limit so
+/// any the VM fails to free show up. Only the VM collects garbage.
+fn check_pins(mut lox: Lox) {
+    lox.set_limits(Limits {
+        heap_bytes: Some(64 * 1024),
+        ..Limits::default()
+    })
+    .expect("set_limits failed");
+    lox.run_source("class P {}").expect("script failed");
+    let kept = lox.eval_expression("P()").expect("eval failed");
+    for _ in 0..5000 {
+        lox.eval_expression("P()").expect("eval failed");
+    }
+    lox.set_global("kept", kept.clone())
+        .expect("set_global failed");
+    assert_eq!(lox.eval_expression("kept").ok(), Some(kept));
+
+    // Arguments to host functions are only pinned if the host keeps them.
+    let held = Rc::new(RefCell::new(Vec::new()));
+    let holder = held.clone();
+    lox.register_function("touch", |_: Value| ());
+    lox.register_function("hold", move |v: Value| holder.borrow_mut().push(v));
+    lox.run_source("var q = P(); q.n = 7; hold(q); q = nil;")
+        .expect("script failed");
+    lox.run_source("var k = 0; while (k < 5000) { touch(P()); k = k + 1; }")
+        .expect("script failed");
+    let q = held.borrow_mut().pop().expect("nothing held");
+    lox.set_global("q", q).expect("set_global failed");
+    assert_eq!(lox.eval_expression("q.n").ok(), Some(Value::Number(7.0)));
+
+    // So are values assigned to native fields.
+    let bag = NativeClass::new("Bag")
+        .constructor(|| Bag { any: Value::Nil })
+        .field(
+            "any",
+            |b: &Bag| b.any.clone(),
+            |b: &mut Bag, v: Value| b.any = v,
+        );
+    lox.register_class(&bag);
+    lox.run_source("var b = Bag(); var k = 0; while (k < 5000) { b.any = P(); k = k + 1; }")
+        .expect("script failed");
+    lox.run_source("b.any.n = 3; var k = 0; while (k < 5000) { touch(P()); k = k + 1; }")
+        .expect("script failed");
+    assert_eq!(
+        lox.eval_expression("b.any.n").ok(),
+        Some(Value::Number(3.0))
+    );
+}
+
+#[test]
+fn tree_walk_interpreter() {
+    check_session(Lox::new());
+    check_host_functions(Lox::new());
+    check_native_classes(Lox::new());
+    check_limits(Lox::new(), false);
+}
+
+#[test]
+fn bytecode_vm() {
+    check_session(Lox::new_vm());
+    check_host_functions(Lox::new_vm());
+    check_native_classes(Lox::new_vm());
+    check_limits(Lox::new_vm(), true);
+    check_pins(Lox::new_vm());
+}
+
+#[test]
+fn bytecode_vm_stress_gc() {
+    let stressed = || {
+        Lox::with_vm_options(VmOptions {
+            stress_gc: true,
+            ..VmOptions::default()
+        })
+    };
+    check_session(stressed());
+    check_host_functions(stressed());
+    check_native_classes(stressed());
+    check_limits(stressed(), true);
+    check_pins(stressed());
+}